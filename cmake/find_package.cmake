@@ -27,8 +27,11 @@ Parameters
   Minimum version of the package to find (optional)
 ``COMPONENTS``
   Semicolon-separated list of components to find (optional)
-``TARGET``
-  The target to resolve (optional)
+``TARGETS``
+  Semicolon-separated list of targets to resolve (optional)
+``LANGUAGES``
+  Semicolon-separated list of languages to enable, or ``NONE`` (optional). When not set,
+  only the C language is enabled, and only if a C compiler is available.
 
 To invoke the script, first copy it into a temporary directory and then run:
 
@@ -41,24 +44,52 @@ To invoke the script, first copy it into a temporary directory and then run:
         -B /path/to/tmp/dir/build
         /path/to/tmp/dir
 
-When `TARGET` is not specified, the script will only call ``find_package()`` and write
-a JSON file with the package name, discovered version and components. When ``TARGET``
-is set, the script will find all the following properties for the target, and also for
+When ``TARGETS`` is not specified, the script will only call ``find_package()`` and write
+a JSON file with the package name, discovered version, components and the list of all
+imported targets defined by the package (requires CMake 3.21 or newer). It also writes the
+``config_files`` array with all package configuration files loaded by ``find_package()``
+(the ``<Pkg>_CONFIG`` variables of the package and its dependencies) together with all
+``*Targets*.cmake`` files next to them, and the ``package_dir`` with ``<Pkg>_DIR``. When ``TARGETS``
+is set, the script will find all the following properties for each of the targets, and also
 recursively for all nested targets referenced by e.g. ``INTERFACE_LINK_LIBRARIES``
-target property:
+target property. All targets are written into a single JSON object under the ``targets``
+member, keyed by the target name. Names of targets that do not exist in the package are
+listed in the ``not_found`` array.
 
 ``NAME``
+``TYPE``
 ``LOCATION``
-``LOCATION_Release``
-``LOCATION_RelWithDebInfo``
-``LOCATION_MinSizeRel``
-``LOCATION_Debug``
+``IMPORTED_LOCATION``
+``IMPORTED_IMPLIB``
+``IMPORTED_CONFIGURATIONS``
+``IMPORTED_LOCATIONS``
+``IMPORTED_IMPLIBS``
+``MAP_IMPORTED_CONFIG``
 ``INTERFACE_COMPILE_DEFINITIONS``
 ``INTERFACE_COMPILE_OPTIONS``
 ``INTERFACE_INCLUDE_DIRECTORIES``
 ``INTERFACE_LINK_DIRECTORIES``
 ``INTERFACE_LINK_LIBRARIES``
 ``INTERFACE_LINK_OPTIONS``
+``GENEX_TARGETS``
+
+``IMPORTED_LOCATIONS`` and ``IMPORTED_IMPLIBS`` are objects with the values of the
+``IMPORTED_LOCATION_<CONFIG>`` and ``IMPORTED_IMPLIB_<CONFIG>`` properties, keyed by the
+upper-case name of the configuration, for all configurations listed in ``IMPORTED_CONFIGURATIONS``,
+for ``CMAKE_BUILD_TYPE`` and for the configurations it is mapped to. ``MAP_IMPORTED_CONFIG``
+is the value of the ``MAP_IMPORTED_CONFIG_<CONFIG>`` property for ``CMAKE_BUILD_TYPE``,
+including empty elements.
+
+Values containing generator expressions are written as they are, with semicolons inside
+the expressions escaped as ``$<SEMICOLON>``. Targets referenced from generator expressions
+in ``INTERFACE_LINK_LIBRARIES`` are resolved into the ``GENEX_TARGETS`` object, keyed by
+the target name. This includes the libraries linked with link features, i.e.
+``$<LINK_LIBRARY:feature,...>`` and ``$<LINK_GROUP:feature,...>``. The ``context`` member
+of the output contains ``PLATFORM_ID`` and the ``<LANG>_COMPILER_ID`` and
+``<LANG>_COMPILER_VERSION`` of the C and C++ compilers (empty when the language is not
+enabled), which are needed to evaluate the generator expressions, and the
+``LINKER_WRAPPER_FLAG`` and ``LINKER_WRAPPER_FLAG_SEP`` of the C compiler (when enabled),
+which are needed to expand the ``LINKER:`` prefix in link options.
 
 Note that due to usage of ``find_package()`` it is not possible to run the script in CMake script
 mode. It must be run in the standard "configure" mode.
@@ -66,11 +97,19 @@ mode. It must be run in the standard "configure" mode.
 #]===================================================================================]
 
 cmake_minimum_required(VERSION ${CMAKE_MIN_VERSION})
-# TODO: Make it possible to disable check for compilers (by passing `LANGUAGES NONE`)
-# so that users do not need to have a C/C++ compiler installed.
-# C compiler is required by FindThreads.cmake that is often used inside other package
-# scripts.
-project(cmake-package)
+if (DEFINED LANGUAGES)
+    project(cmake-package LANGUAGES ${LANGUAGES})
+else()
+    # Packages that only ship prebuilt libraries don't need any compiler, but C compiler is
+    # required by FindThreads.cmake that is often used inside other package scripts, so enable
+    # it if it's available.
+    project(cmake-package LANGUAGES NONE)
+    include(CheckLanguage)
+    check_language(C)
+    if (CMAKE_C_COMPILER)
+        enable_language(C)
+    endif()
+endif()
 
 
 ###################################################################################
@@ -106,6 +145,33 @@ function(find_package_wrapper)
             string(REPLACE ";" "\",\"" component_array "${FP_COMPONENTS}")
             string(JSON json SET ${json} "components" "[\"${component_array}\"]")
         endif()
+        # List all imported targets the package (and its dependencies) has defined. The
+        # IMPORTED_TARGETS directory property is only available since CMake 3.21.
+        if (CMAKE_VERSION VERSION_GREATER_EQUAL 3.21)
+            get_directory_property(imported_targets IMPORTED_TARGETS)
+            list_to_json(json ${json} "targets" imported_targets)
+        endif()
+
+        # Files used to find the package, so that the build script can be rerun when they change.
+        # find_package() sets <Pkg>_CONFIG for the package and all its dependencies found in
+        # config mode, the *Targets*.cmake files are included by the config files.
+        set(config_files)
+        get_cmake_property(variables VARIABLES)
+        foreach(variable ${variables})
+            if (variable MATCHES "_CONFIG$" AND "${${variable}}" MATCHES "\\.cmake$" AND EXISTS "${${variable}}")
+                list(APPEND config_files "${${variable}}")
+                get_filename_component(config_dir "${${variable}}" DIRECTORY)
+                file(GLOB targets_files "${config_dir}/*Targets*.cmake")
+                list(APPEND config_files ${targets_files})
+            endif()
+        endforeach()
+        if (config_files)
+            list(REMOVE_DUPLICATES config_files)
+            list_to_json(json ${json} "config_files" config_files)
+        endif()
+        if (${FP_PACKAGE}_DIR)
+            string(JSON json SET ${json} "package_dir" "\"${${FP_PACKAGE}_DIR}\"")
+        endif()
 
         file(WRITE ${FP_OUTPUT_FILE} ${json})
     else()
@@ -118,8 +184,15 @@ endfunction()
 # For given target and a target property this function resolves the value of the
 # property. It checks each value and if the value is in fact another target, it
 # calls `resolve_deps_recursively()` on it to obtain all properties of the target,
-# otherwise it just keeps the value. Generator expressions are ignored since they
-# cannot be resolved at configuration time.
+# otherwise it just keeps the value.
+#
+# Generator expressions cannot be evaluated at configuration time, so they are kept
+# as they are and evaluated later by the Rust code. CMake splits the property value
+# on every semicolon, including those inside generator expressions, so the pieces of
+# such expressions are joined back and the semicolons are escaped as `$<SEMICOLON>`.
+# If `GENEX_TARGETS_VAR` is given, all targets referenced by generator expressions
+# are resolved as well and stored in the JSON object in the variable, keyed by the
+# target name, so that the Rust code can look them up after evaluation.
 #
 # The result is a list of either strings or JSON objects (as a string). It is stored
 # in the provided `OUT_VAR` variable.
@@ -129,27 +202,76 @@ endfunction()
 #   TARGET: The target to resolve (required)
 #   PROPERTY: The property to resolve (required)
 #   OUT_VAR: The variable to store the result in (required)
+#   GENEX_TARGETS_VAR: The variable with JSON object of resolved targets referenced by
+#                      generator expressions (optional)
 ###################################################################################
 function(resolve_target_prop)
-    cmake_parse_arguments(ARG "" "TARGET;PROPERTY;OUT_VAR" "" ${ARGN})
+    cmake_parse_arguments(ARG "" "TARGET;PROPERTY;OUT_VAR;GENEX_TARGETS_VAR" "" ${ARGN})
+    if (ARG_GENEX_TARGETS_VAR)
+        set(genex_targets "${${ARG_GENEX_TARGETS_VAR}}")
+    endif()
     # Read the property value
     get_target_property(prop_values ${ARG_TARGET} ${ARG_PROPERTY})
     # Check each value
     message(STATUS "${ARG_TARGET}: ${ARG_PROPERTY} = ${prop_values}")
     set(result)
+    set(pending)
     foreach(value ${prop_values})
+        # Join the value with the preceding piece of an unterminated generator expression
+        if (pending)
+            set(value "${pending}$<SEMICOLON>${value}")
+            set(pending)
+        endif()
+        if ("${value}" MATCHES "\\$<")
+            string(REGEX MATCHALL "\\$<" opening "${value}")
+            string(REGEX MATCHALL ">" closing "${value}")
+            list(LENGTH opening opening_count)
+            list(LENGTH closing closing_count)
+            if (opening_count GREATER closing_count)
+                set(pending "${value}")
+                continue()
+            endif()
+        endif()
+
         # If the value is actually another imported target, then recursive into it and obtain
         # all properties of the target. Don't recurse into ourselves.
         if (TARGET ${value} AND NOT ("${ARG_TARGET}" STREQUAL "${value}"))
             set(var)
             resolve_deps_recursively(TARGET ${value} OUTPUT_JSON var)
             list(APPEND result ${var})
-        elseif(value AND NOT ("${value}" MATCHES "^\\$\\<.*")) # Ignore generator expressions
+        elseif("${value}" MATCHES "\\$<")
+            # Keep generator expressions for the Rust code to evaluate
+            list(APPEND result "${value}")
+            if (ARG_GENEX_TARGETS_VAR)
+                # Resolve everything that looks like a target name inside the expression
+                string(REGEX MATCHALL "[A-Za-z0-9_.+-]+(::[A-Za-z0-9_.+-]+)*" tokens "${value}")
+                foreach(token ${tokens})
+                    if (NOT TARGET ${token} OR "${ARG_TARGET}" STREQUAL "${token}")
+                        continue()
+                    endif()
+                    string(JSON existing ERROR_VARIABLE error GET "${genex_targets}" "${token}")
+                    if (error STREQUAL "NOTFOUND")
+                        continue() # Already resolved
+                    endif()
+                    set(var)
+                    resolve_deps_recursively(TARGET ${token} OUTPUT_JSON var)
+                    string(JSON genex_targets SET "${genex_targets}" "${token}" "${var}")
+                endforeach()
+            endif()
+        elseif(value)
             # Otherwise just append the value to output the list
             list(APPEND result ${value})
         endif()
     endforeach()
+    if (pending)
+        # Keep the unterminated generator expression rather than losing the value
+        message(WARNING "${ARG_TARGET}: ${ARG_PROPERTY} has an unterminated generator expression: ${pending}")
+        list(APPEND result "${pending}")
+    endif()
     set(${ARG_OUT_VAR} ${result} PARENT_SCOPE)
+    if (ARG_GENEX_TARGETS_VAR)
+        set(${ARG_GENEX_TARGETS_VAR} "${genex_targets}" PARENT_SCOPE)
+    endif()
 endfunction()
 
 ###################################################################################
@@ -165,7 +287,9 @@ function(resolve_deps_recursively)
     cmake_parse_arguments(ARG "" "TARGET;OUTPUT_JSON" "" ${ARGN})
     set(single_value_props
         NAME
+        TYPE
         LOCATION
+        IMPORTED_LOCATION
         IMPORTED_IMPLIB
         IMPORTED_NO_SONAME
     )
@@ -179,21 +303,6 @@ function(resolve_deps_recursively)
         INTERFACE_LINK_DEPENDENT_LIBRARIES
         INTERFACE_LINK_OPTIONS
     )
-    set(cfg_props
-        LOCATION
-        IMPORTED_IMPLIB
-    )
-    set(cfg_types
-        Release
-        RelWithDebInfo
-        MinSizeRel
-        Debug
-    )
-    foreach(cfg_prop ${cfg_props})
-        foreach(config ${cfg_types})
-            list(APPEND single_value_props "${cfg_prop}_${config}")
-        endforeach()
-    endforeach()
 
     set(json "{}")
     foreach(prop ${single_value_props})
@@ -205,13 +314,63 @@ function(resolve_deps_recursively)
         endif()
     endforeach()
 
+    # The Rust code selects the configuration to use the same way CMake does, so it needs
+    # the locations of all configurations that could be selected.
+    get_target_property(configs ${ARG_TARGET} IMPORTED_CONFIGURATIONS)
+    if (configs)
+        list_to_json(json ${json} IMPORTED_CONFIGURATIONS configs)
+    else()
+        set(configs)
+    endif()
+    string(TOUPPER "${CMAKE_BUILD_TYPE}" build_type)
+    get_target_property(mapped_configs ${ARG_TARGET} MAP_IMPORTED_CONFIG_${build_type})
+    if (mapped_configs)
+        # Empty elements select the configuration-less properties, so they must be kept
+        set(i 0)
+        string(JSON json SET ${json} MAP_IMPORTED_CONFIG "[]")
+        foreach(config IN LISTS mapped_configs)
+            string(JSON json SET ${json} MAP_IMPORTED_CONFIG ${i} "\"${config}\"")
+            math(EXPR i "${i} + 1")
+        endforeach()
+    else()
+        set(mapped_configs)
+    endif()
+    foreach(prop IMPORTED_LOCATION IMPORTED_IMPLIB)
+        set(config_json "{}")
+        foreach(config ${configs} ${CMAKE_BUILD_TYPE} ${mapped_configs})
+            string(TOUPPER "${config}" config)
+            get_target_property(value ${ARG_TARGET} ${prop}_${config})
+            message(STATUS "${ARG_TARGET}: ${prop}_${config} = ${value}")
+            if (value)
+                string(JSON config_json SET "${config_json}" "${config}" "\"${value}\"")
+            endif()
+        endforeach()
+        if (NOT config_json STREQUAL "{}")
+            string(JSON json SET "${json}" "${prop}S" "${config_json}")
+        endif()
+    endforeach()
+
+    set(target_genex_targets "{}")
     foreach(prop ${multi_value_props})
         set(value)
-        resolve_target_prop(TARGET ${ARG_TARGET} PROPERTY ${prop} OUT_VAR value)
+        if (prop MATCHES "LINK_LIBRARIES")
+            # Only link libraries can reference other targets
+            resolve_target_prop(
+                TARGET ${ARG_TARGET}
+                PROPERTY ${prop}
+                OUT_VAR value
+                GENEX_TARGETS_VAR target_genex_targets
+            )
+        else()
+            resolve_target_prop(TARGET ${ARG_TARGET} PROPERTY ${prop} OUT_VAR value)
+        endif()
         if (value)
             list_to_json(json ${json} ${prop} value)
         endif()
     endforeach()
+    if (NOT target_genex_targets STREQUAL "{}")
+        string(JSON json SET "${json}" "GENEX_TARGETS" "${target_genex_targets}")
+    endif()
     set(${ARG_OUTPUT_JSON} ${json} PARENT_SCOPE)
 
 endfunction()
@@ -243,24 +402,24 @@ function(list_to_json json_var json member list_var)
 endfunction()
 
 ###################################################################################
-# Invokes find_package(), locates the specified target and returns all relevant
-# properties of the target and all targets that may be referenced by any of the
+# Invokes find_package(), locates the specified targets and returns all relevant
+# properties of the targets and all targets that may be referenced by any of the
 # properties (recursively).
 #
 # Parameters:
 #   PACKAGE: The package name to find (required)
-#   TARGET: The target to resolve (required)
+#   TARGETS: The targets to resolve (required)
 #   OUTPUT_FILE: The file to write the JSON output to (required)
 #   COMPONENTS: The components to find (optional)
 #   VERSION: The minimum version of the package to find (optional)
 ###################################################################################
-function (find_package_target)
-    cmake_parse_arguments(ARG "" "PACKAGE;VERSION;TARGET;OUTPUT_FILE" "COMPONENTS" ${ARGN})
+function (find_package_targets)
+    cmake_parse_arguments(ARG "" "PACKAGE;VERSION;OUTPUT_FILE" "TARGETS;COMPONENTS" ${ARGN})
     if (NOT ARG_PACKAGE)
         message(FATAL_ERROR "PACKAGE argument is not set")
     endif()
-    if (NOT ARG_TARGET)
-        message(FATAL_ERROR "TARGET argument is not set")
+    if (NOT ARG_TARGETS)
+        message(FATAL_ERROR "TARGETS argument is not set")
     endif()
     if (NOT ARG_OUTPUT_FILE)
         message(FATAL_ERROR "OUTPUT_FILE argument is not set")
@@ -270,15 +429,44 @@ function (find_package_target)
     # the version is recent enough.
     find_package(${ARG_PACKAGE} ${ARG_VERSION} COMPONENTS ${ARG_COMPONENTS})
     if (${ARG_PACKAGE}_FOUND)
-        resolve_deps_recursively(
-            TARGET ${ARG_TARGET}
-            OUTPUT_JSON json
-        )
-        file(WRITE ${ARG_OUTPUT_FILE} ${json})
+        set(json "{ \"targets\": { }, \"not_found\": [ ] }")
+        foreach(target ${ARG_TARGETS})
+            if (NOT TARGET ${target})
+                # Report the missing target explicitly, so that the Rust code can tell it apart
+                # from a failure of the script.
+                message(STATUS "Target ${target} not found in package ${ARG_PACKAGE}")
+                string(JSON count LENGTH "${json}" "not_found")
+                string(JSON json SET "${json}" "not_found" ${count} "\"${target}\"")
+                continue()
+            endif()
+            set(target_json)
+            resolve_deps_recursively(
+                TARGET ${target}
+                OUTPUT_JSON target_json
+            )
+            string(JSON json SET "${json}" "targets" "${target}" "${target_json}")
+        endforeach()
+
+        # Information needed by the Rust code to evaluate generator expressions
+        set(context "{ }")
+        string(JSON context SET "${context}" "PLATFORM_ID" "\"${CMAKE_SYSTEM_NAME}\"")
+        # The values are empty for languages that are not enabled
+        foreach(lang C CXX)
+            string(JSON context SET "${context}" "${lang}_COMPILER_ID" "\"${CMAKE_${lang}_COMPILER_ID}\"")
+            string(JSON context SET "${context}" "${lang}_COMPILER_VERSION" "\"${CMAKE_${lang}_COMPILER_VERSION}\"")
+        endforeach()
+        # Rust links through the C compiler driver, so LINKER: options are wrapped for it
+        if (CMAKE_C_COMPILER_ID)
+            string(JSON context SET "${context}" "LINKER_WRAPPER_FLAG" "\"${CMAKE_C_LINKER_WRAPPER_FLAG}\"")
+            string(JSON context SET "${context}" "LINKER_WRAPPER_FLAG_SEP" "\"${CMAKE_C_LINKER_WRAPPER_FLAG_SEP}\"")
+        endif()
+        string(JSON json SET "${json}" "context" "${context}")
+
+        file(WRITE ${ARG_OUTPUT_FILE} "${json}")
         message(STATUS "Target details written to ${ARG_OUTPUT_FILE}")
     else()
         # We found the package before, how come we did not find it this time?!
-        message(FATAL_ERROR "Package ${FP_PACKAGE} not found")
+        message(FATAL_ERROR "Package ${ARG_PACKAGE} not found")
     endif()
 endfunction()
 
@@ -296,7 +484,7 @@ endif()
 
 message(STATUS "CMAKE_BUILD_TYPE=${CMAKE_BUILD_TYPE}")
 
-if (NOT DEFINED TARGET)
+if (NOT DEFINED TARGETS)
     find_package_wrapper(
         PACKAGE ${PACKAGE}
         COMPONENTS "${COMPONENTS}"
@@ -304,11 +492,11 @@ if (NOT DEFINED TARGET)
         OUTPUT_FILE ${OUTPUT_FILE}
     )
 else()
-    find_package_target(
+    find_package_targets(
         PACKAGE ${PACKAGE}
         COMPONENTS "${COMPONENTS}"
         VERSION ${VERSION}
-        TARGET ${TARGET}
+        TARGETS "${TARGETS}"
         OUTPUT_FILE ${OUTPUT_FILE}
     )
 endif()
@@ -0,0 +1,201 @@
+// SPDX-FileCopyrightText: 2024 Daniel Vrátil <dvratil@kde.org>
+//
+// SPDX-License-Identifier: MIT
+
+//! Persistent cache of the results of CMake runs.
+//!
+//! Build scripts are rerun whenever anything in the crate changes, and resolving a package
+//! and its targets takes several CMake runs, each of which configures a whole project. The
+//! results are therefore stored on disk and reused as long as everything that could affect
+//! them stays the same.
+//!
+//! Each entry is stored in its own JSON file named after a hash of its key. The key describes
+//! the request (the `cmake` executable, the package and all the options passed to CMake) and
+//! the values of the environment variables that affect the search. The entry also records
+//! the modification times of the files the result was derived from (package configuration
+//! files, libraries), and is discarded when any of them changes.
+
+use std::collections::BTreeMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// Environment variable that overrides the location of the cache, e.g. to share it between
+/// all crates built in the same target directory.
+pub(crate) const CACHE_DIR_ENV: &str = "CMAKE_PACKAGE_CACHE_DIR";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry<T> {
+    key: String,
+    files: BTreeMap<PathBuf, Option<SystemTime>>,
+    value: T,
+}
+
+/// A directory with cached results.
+#[derive(Debug, Clone)]
+pub(crate) struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    /// Returns the cache in the `dir` directory, or in the directory given by the
+    /// `CMAKE_PACKAGE_CACHE_DIR` environment variable, or in `OUT_DIR`, in that order.
+    /// Returns `None` if none of them is set.
+    pub(crate) fn new(dir: Option<&Path>) -> Option<Self> {
+        let dir = dir.map(Path::to_path_buf).or_else(|| {
+            std::env::var_os(CACHE_DIR_ENV)
+                .filter(|dir| !dir.is_empty())
+                .map(PathBuf::from)
+                .or_else(|| {
+                    std::env::var_os("OUT_DIR")
+                        .map(|dir| PathBuf::from(dir).join("cmake-package-cache"))
+                })
+        })?;
+        Some(Self { dir })
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        self.dir.join(format!("{:016x}.json", hasher.finish()))
+    }
+
+    /// Returns the cached value for the `key`, if there is one and none of the files it was
+    /// derived from has changed since.
+    pub(crate) fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let reader = std::fs::File::open(self.entry_path(key)).ok()?;
+        let entry: Entry<T> = serde_json::from_reader(std::io::BufReader::new(reader)).ok()?;
+        // The hash might collide, so the key itself must match as well
+        if entry.key != key {
+            return None;
+        }
+        entry
+            .files
+            .iter()
+            .all(|(path, modified)| modification_time(path) == *modified)
+            .then_some(entry.value)
+    }
+
+    /// Stores the `value` for the `key`, together with the modification times of the `files`
+    /// it was derived from.
+    ///
+    /// The cache is only an optimization, so failing to write the entry is not an error.
+    pub(crate) fn put<T: Serialize>(
+        &self,
+        key: &str,
+        files: impl IntoIterator<Item = impl Into<PathBuf>>,
+        value: &T,
+    ) {
+        let entry = Entry {
+            key: key.to_string(),
+            files: files
+                .into_iter()
+                .map(Into::into)
+                .map(|path| {
+                    let modified = modification_time(&path);
+                    (path, modified)
+                })
+                .collect(),
+            value,
+        };
+        let Ok(json) = serde_json::to_vec(&entry) else {
+            return;
+        };
+        if std::fs::create_dir_all(&self.dir).is_err() {
+            return;
+        }
+        // Write the entry atomically, the cache may be shared by build scripts running in parallel
+        let path = self.entry_path(key);
+        let Ok(mut file) = tempfile::NamedTempFile::new_in(&self.dir) else {
+            return;
+        };
+        if std::io::Write::write_all(&mut file, &json).is_ok() {
+            let _ = file.persist(path);
+        }
+    }
+}
+
+fn modification_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+}
+
+/// Builds a cache key from the `parts` of the request and the values of the environment
+/// variables `env_vars`.
+pub(crate) fn key(
+    parts: impl IntoIterator<Item = impl AsRef<str>>,
+    env_vars: impl IntoIterator<Item = impl AsRef<str>>,
+) -> String {
+    parts
+        .into_iter()
+        .map(|part| part.as_ref().to_string())
+        .chain(env_vars.into_iter().map(|var| {
+            let var = var.as_ref();
+            format!(
+                "{var}={}",
+                std::env::var_os(var).unwrap_or_default().to_string_lossy()
+            )
+        }))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+
+    #[test]
+    fn get_put() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(Some(dir.path())).unwrap();
+        let file = dir.path().join("FooConfig.cmake");
+        std::fs::write(&file, "").unwrap();
+
+        assert_eq!(cache.get::<Vec<String>>("foo"), None);
+        cache.put("foo", [&file], &vec!["Foo::Foo".to_string()]);
+        assert_eq!(
+            cache.get::<Vec<String>>("foo"),
+            Some(vec!["Foo::Foo".to_string()])
+        );
+        assert_eq!(cache.get::<Vec<String>>("bar"), None);
+
+        // Changing a file invalidates the entry
+        let modified = SystemTime::now() + std::time::Duration::from_secs(10);
+        std::fs::File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        assert_eq!(cache.get::<Vec<String>>("foo"), None);
+
+        // ...as well as removing it
+        cache.put("foo", [&file], &vec!["Foo::Foo".to_string()]);
+        assert!(cache.get::<Vec<String>>("foo").is_some());
+        std::fs::remove_file(&file).unwrap();
+        assert_eq!(cache.get::<Vec<String>>("foo"), None);
+    }
+
+    #[test]
+    fn missing_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(Some(dir.path())).unwrap();
+        let file = dir.path().join("FooConfig.cmake");
+
+        // A file that did not exist must not appear either
+        cache.put("foo", [&file], &1);
+        assert_eq!(cache.get::<i32>("foo"), Some(1));
+        std::fs::write(&file, "").unwrap();
+        assert_eq!(cache.get::<i32>("foo"), None);
+    }
+
+    #[test]
+    fn key_with_env() {
+        let key = key(["package", "Foo"], ["CMAKE_PACKAGE_TEST_UNSET_VARIABLE"]);
+        assert_eq!(key, "package\nFoo\nCMAKE_PACKAGE_TEST_UNSET_VARIABLE=");
+    }
+}
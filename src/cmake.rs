@@ -2,13 +2,24 @@
 //
 // SPDX-License-Identifier: MIT
 
+use crate::cache::{self, Cache};
+use crate::compiler;
+use crate::genex;
+use crate::link::{LibraryKind, LinkLibrary, LinkPreference, TargetPlatform};
+use crate::lock::{RECORD_ENV, REPLAY_ENV};
+use crate::options;
+use crate::toolchain;
 use crate::version::{Version, VersionError};
-use crate::{CMakePackage, CMakeTarget};
+use crate::{CMakeExecutable, CMakePackage, CMakeTarget, Source, TargetType};
 
 use itertools::Itertools;
-use serde::Deserialize;
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
+use std::process::{Command, Output};
 use tempfile::TempDir;
 use which::which;
 
@@ -24,12 +35,22 @@ pub struct CMakeProgram {
     pub version: Version,
 }
 
-fn script_path(script: &str) -> PathBuf {
-    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
-        .join("cmake")
-        .join(script)
+/// The `cmake` program and the working directory with the CMake project that found a package.
+/// The targets of the package are resolved by running CMake in the same project again.
+#[derive(Debug)]
+pub(crate) struct Project {
+    pub(crate) cmake: CMakeProgram,
+    pub(crate) working_directory: TempDir,
 }
 
+/// The CMake project used to find the package and resolve its targets.
+///
+/// The scripts are embedded into the crate, so that they are available even when the sources
+/// of the crate are not present on the machine running the build script.
+const FIND_PACKAGE_SCRIPT: &str = include_str!("../cmake/find_package.cmake");
+/// The CMake script that prints the version of CMake.
+const CMAKE_VERSION_SCRIPT: &str = include_str!("../cmake/cmake_version.cmake");
+
 /// Errors that can occur while working with CMake.
 #[derive(Debug)]
 pub enum Error {
@@ -37,21 +58,154 @@ pub enum Error {
     CMakeNotFound,
     /// The available CMake version is too old (see [`CMAKE_MIN_VERSION`]).
     UnsupportedCMakeVersion,
-    /// An internal error in the library.
-    Internal,
-    /// An I/O error while executing `cmake`
+    /// An I/O error while executing `cmake` or accessing its output.
     IO(std::io::Error),
+    /// Failed to create the temporary working directory for CMake.
+    TempDir(std::io::Error),
+    /// The `cmake` command exited with an error.
+    ScriptFailed {
+        /// The command line that was executed.
+        command: String,
+        /// Exit code of the command, `None` if it was terminated by a signal.
+        status: Option<i32>,
+        /// Captured standard output of the command.
+        stdout: String,
+        /// Captured standard error output of the command.
+        stderr: String,
+    },
+    /// The JSON output produced by the CMake script could not be parsed.
+    InvalidOutput {
+        /// Path to the output file.
+        path: PathBuf,
+        /// The underlying parse error.
+        source: serde_json::Error,
+    },
     /// An version-related error (e.g. the found package version is too old)
     Version(VersionError),
     /// The requested package was not found by CMake.
     PackageNotFound,
+    /// The requested target does not exist in the package.
+    TargetNotFound {
+        /// Name of the package that was searched.
+        package: String,
+        /// Name of the target that was not found.
+        target: String,
+        /// Names of the targets available in the package (see [`CMakePackage::available_targets()`]).
+        available: Vec<String>,
+    },
+    /// The requested target is not an executable (see [`CMakePackage::executable()`]).
+    NotAnExecutable {
+        /// Name of the target.
+        target: String,
+        /// Actual type of the target.
+        target_type: TargetType,
+    },
+    /// The imported target has no location for the current build type.
+    LocationNotFound {
+        /// Name of the target.
+        target: String,
+    },
+    /// CMake could not find a compiler for a language required to find the package
+    /// (see [`FindPackageBuilder::languages()`][crate::FindPackageBuilder::languages]).
+    CompilerNotFound {
+        /// The CMake name of the language (e.g. `C` or `CXX`).
+        language: String,
+        /// Captured standard error output of CMake.
+        stderr: String,
+    },
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::CMakeNotFound => write!(f, "cmake executable not found in PATH"),
+            Error::UnsupportedCMakeVersion => write!(
+                f,
+                "unsupported CMake version, at least {CMAKE_MIN_VERSION} is required"
+            ),
+            Error::IO(err) => write!(f, "I/O error while running cmake: {err}"),
+            Error::TempDir(err) => write!(f, "failed to create working directory: {err}"),
+            Error::ScriptFailed {
+                command,
+                status,
+                stderr,
+                ..
+            } => {
+                match status {
+                    Some(status) => write!(f, "`{command}` failed with exit code {status}")?,
+                    None => write!(f, "`{command}` was terminated by a signal")?,
+                }
+                if !stderr.trim().is_empty() {
+                    write!(f, ":\n{}", stderr.trim_end())?;
+                }
+                Ok(())
+            }
+            Error::InvalidOutput { path, source } => {
+                write!(f, "failed to parse {}: {source}", path.display())
+            }
+            Error::Version(err) => write!(f, "{err}"),
+            Error::PackageNotFound => write!(f, "package not found"),
+            Error::TargetNotFound {
+                package,
+                target,
+                available,
+            } => {
+                write!(f, "target {target} not found in package {package}")?;
+                if !available.is_empty() {
+                    write!(f, " (available targets: {})", available.join(", "))?;
+                }
+                Ok(())
+            }
+            Error::NotAnExecutable {
+                target,
+                target_type,
+            } => write!(f, "target {target} is not an executable ({target_type:?})"),
+            Error::LocationNotFound { target } => {
+                write!(
+                    f,
+                    "target {target} has no location for the current build type"
+                )
+            }
+            Error::CompilerNotFound { language, .. } => write!(
+                f,
+                "no compiler for language {language} found, install one or set the languages \
+                 to enable with FindPackageBuilder::languages()"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::IO(err) | Error::TempDir(err) => Some(err),
+            Error::InvalidOutput { source, .. } => Some(source),
+            Error::Version(err) => Some(err),
+            _ => None,
+        }
+    }
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 struct PackageResult {
     name: Option<String>,
     version: Option<String>,
     components: Option<Vec<String>>,
+    targets: Option<Vec<String>>,
+    config_files: Option<Vec<String>>,
+    package_dir: Option<String>,
+}
+
+impl PackageResult {
+    /// Paths to the package configuration files and the package directory.
+    fn config_paths(&self) -> Vec<PathBuf> {
+        self.config_files
+            .iter()
+            .flatten()
+            .chain(&self.package_dir)
+            .map(PathBuf::from)
+            .collect()
+    }
 }
 
 /// Find the CMake program on the system and check version compatibility.
@@ -63,12 +217,40 @@ struct PackageResult {
 /// is not found or [`Error::UnsupportedCMakeVersion`] when the version is too low.
 pub fn find_cmake() -> Result<CMakeProgram, Error> {
     let path = which("cmake").or(Err(Error::CMakeNotFound))?;
+    cmake_program(path)
+}
+
+/// Same as [`find_cmake()`], but the version of the `cmake` executable is looked up in the `cache`
+/// first, so that it does not have to be run.
+fn find_cmake_cached(cache: Option<&Cache>) -> Result<CMakeProgram, Error> {
+    let Some(cache) = cache else {
+        return find_cmake();
+    };
+
+    let path = which("cmake").or(Err(Error::CMakeNotFound))?;
+    let key = cache::key(["cmake".into(), path.to_string_lossy()], [""; 0]);
+    if let Some(version) = cache
+        .get::<String>(&key)
+        .and_then(|version| Version::parse(&version).ok())
+    {
+        return Ok(CMakeProgram { path, version });
+    }
+
+    let cmake = cmake_program(path)?;
+    cache.put(&key, [&cmake.path], &cmake.version.to_string());
+    Ok(cmake)
+}
 
-    let output = Command::new(&path)
-        .arg("-P")
-        .arg(script_path("cmake_version.cmake"))
-        .output()
-        .or(Err(Error::Internal))?;
+fn cmake_program(path: PathBuf) -> Result<CMakeProgram, Error> {
+    let mut script = tempfile::Builder::new()
+        .prefix("cmake_version")
+        .suffix(".cmake")
+        .tempfile()
+        .map_err(Error::TempDir)?;
+    script
+        .write_all(CMAKE_VERSION_SCRIPT.as_bytes())
+        .map_err(Error::IO)?;
+    let output = run(Command::new(&path).arg("-P").arg(script.path()), false)?;
 
     let version = String::from_utf8_lossy(&output.stderr)
         .trim()
@@ -99,34 +281,337 @@ fn get_temporary_working_directory() -> Result<TempDir, Error> {
     tempfile::Builder::new()
         .prefix("cmake-package-rs")
         .tempdir_in(out_dir)
-        .or(Err(Error::Internal))
+        .map_err(Error::TempDir)
+}
+
+/// Writes the `CMakeLists.txt` into the `working_directory`, either the built-in one or the
+/// custom `script` provided by the user.
+fn setup_cmake_project(working_directory: &Path, script: Option<&Path>) -> Result<(), Error> {
+    let destination = working_directory.join("CMakeLists.txt");
+    match script {
+        Some(script) => std::fs::copy(script, destination).map(|_| ()),
+        None => std::fs::write(destination, FIND_PACKAGE_SCRIPT),
+    }
+    .map_err(Error::IO)
+}
+
+/// Options that are applied to every CMake invocation made on behalf of a package, so that
+/// resolving the package and resolving its targets always operate in the same environment.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct CMakeOptions {
+    /// Whether to forward output of `cmake` to the build script's output.
+    pub(crate) verbose: bool,
+    /// Additional prefixes to search for the package, passed as `CMAKE_PREFIX_PATH`.
+    pub(crate) prefix_paths: Vec<PathBuf>,
+    /// Directory containing the package configuration file, passed as `<Pkg>_DIR`.
+    pub(crate) package_dir: Option<PathBuf>,
+    /// Installation prefix of the package, passed as `<Pkg>_ROOT`.
+    pub(crate) package_root: Option<PathBuf>,
+    /// Arbitrary cache variables, passed as `-D<key>=<value>`.
+    pub(crate) defines: Vec<(String, String)>,
+    /// Preferred kind of libraries to link against.
+    pub(crate) link_preference: LinkPreference,
+    /// Build type requested by the user, replaced with the resolved build type by `find_package()`.
+    pub(crate) build_type: Option<CMakeBuildType>,
+    /// Directory to store the cached results in, instead of the default one.
+    pub(crate) cache_dir: Option<PathBuf>,
+    /// Whether to always run CMake instead of using the cached results.
+    pub(crate) no_cache: bool,
+    /// Custom script to use instead of the built-in `find_package.cmake`.
+    pub(crate) script: Option<PathBuf>,
+    /// Languages to enable in the CMake project, `None` to let the script decide.
+    pub(crate) languages: Option<Vec<String>>,
+    /// Toolchain file requested by the user, replaced with the resolved toolchain file by `find_package()`.
+    pub(crate) toolchain_file: Option<PathBuf>,
 }
 
-fn setup_cmake_project(working_directory: &Path) -> Result<(), Error> {
-    std::fs::copy(
-        script_path("find_package.cmake"),
-        working_directory.join("CMakeLists.txt"),
-    )
-    .map_err(Error::IO)?;
-    Ok(())
+impl CMakeOptions {
+    /// The build type to use for the package.
+    fn build_type(&self) -> CMakeBuildType {
+        self.build_type.clone().unwrap_or_else(build_type)
+    }
+
+    /// The cache of results of CMake runs, if enabled.
+    fn cache(&self) -> Option<Cache> {
+        if self.no_cache {
+            return None;
+        }
+        Cache::new(self.cache_dir.as_deref())
+    }
+
+    /// Describes the options that affect the result of the search for the `package`, so that
+    /// results found with different options can be told apart.
+    ///
+    /// The generated toolchain file is left out, it is relative and derived from the environment.
+    pub(crate) fn describe(&self, package: &str) -> Vec<String> {
+        let options = Self {
+            toolchain_file: self
+                .toolchain_file
+                .clone()
+                .filter(|file| file.is_absolute()),
+            ..self.clone()
+        };
+        let mut command = Command::new("cmake");
+        command.arg(format!("-DCMAKE_BUILD_TYPE={}", self.build_type()));
+        options.apply(package, &mut command);
+        command
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .chain([format!("{:?}", self.link_preference)])
+            .collect()
+    }
+
+    /// Adds the `-D` arguments for the options to the `command`.
+    fn apply(&self, package: &str, command: &mut Command) {
+        if !self.prefix_paths.is_empty() {
+            command.arg(format!(
+                "-DCMAKE_PREFIX_PATH={}",
+                self.prefix_paths
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .join(";")
+            ));
+        }
+        if let Some(package_dir) = &self.package_dir {
+            command.arg(format!("-D{}_DIR={}", package, package_dir.display()));
+        }
+        if let Some(package_root) = &self.package_root {
+            command.arg(format!("-D{}_ROOT={}", package, package_root.display()));
+        }
+        for (key, value) in &self.defines {
+            command.arg(format!("-D{}={}", key, value));
+        }
+        if let Some(toolchain_file) = &self.toolchain_file {
+            command.arg(format!(
+                "-DCMAKE_TOOLCHAIN_FILE={}",
+                toolchain_file.display()
+            ));
+        }
+        match &self.languages {
+            Some(languages) if languages.is_empty() => command.arg("-DLANGUAGES=NONE"),
+            Some(languages) => command.arg(format!("-DLANGUAGES={}", languages.join(";"))),
+            None => command,
+        };
+    }
 }
 
-fn stdio(verbose: bool) -> Stdio {
+/// Runs the `command` and captures its output.
+///
+/// In verbose mode, the captured output is forwarded to the standard output and standard error
+/// of the build script. Returns [`Error::ScriptFailed`] when the command exits with an error.
+fn run(command: &mut Command, verbose: bool) -> Result<Output, Error> {
+    let output = command.output().map_err(Error::IO)?;
     if verbose {
-        Stdio::inherit()
-    } else {
-        Stdio::null()
+        // Failing to forward the output is not a reason to fail the whole build
+        let _ = std::io::stdout().write_all(&output.stdout);
+        let _ = std::io::stderr().write_all(&output.stderr);
+    }
+
+    if !output.status.success() {
+        return Err(Error::ScriptFailed {
+            command: std::iter::once(command.get_program())
+                .chain(command.get_args())
+                .map(|arg| arg.to_string_lossy())
+                .join(" "),
+            status: output.status.code(),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+
+    Ok(output)
+}
+
+/// Turns a failure of CMake caused by a missing compiler into [`Error::CompilerNotFound`],
+/// any other error is returned as it is.
+fn compiler_not_found(err: Error) -> Error {
+    let Error::ScriptFailed { stderr, .. } = &err else {
+        return err;
+    };
+    let language = Regex::new(r"No CMAKE_([A-Za-z0-9_]+)_COMPILER could be found")
+        .unwrap()
+        .captures(stderr)
+        .map(|captures| captures[1].to_string())
+        .or_else(|| {
+            // FindThreads needs a compiler, but C isn't enabled when no C compiler is available
+            stderr
+                .contains("FindThreads only works if either C or CXX language is enabled")
+                .then(|| "C".to_string())
+        });
+    match language {
+        Some(language) => Error::CompilerNotFound {
+            language,
+            stderr: stderr.clone(),
+        },
+        None => err,
+    }
+}
+
+/// Reads and parses the JSON file written by the CMake script.
+pub(crate) fn read_output<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T, Error> {
+    let reader = std::fs::File::open(path).map_err(Error::IO)?;
+    serde_json::from_reader(reader).map_err(|source| Error::InvalidOutput {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Runs the CMake `command` that writes its result into `output_file` and parses the result.
+///
+/// The result is looked up in the cache first, keyed on the `command` (without the output file)
+/// and the environment variables that affect the `package`. The cached result is only used if
+/// none of the files returned by `files` for the result has changed since. Results for which
+/// `files` returns `None` are not cached.
+fn run_cached<T: Serialize + DeserializeOwned>(
+    command: &mut Command,
+    output_file: &Path,
+    package: &str,
+    options: &CMakeOptions,
+    files: impl Fn(&T) -> Option<Vec<PathBuf>>,
+) -> Result<T, Error> {
+    let cache = options.cache();
+    // The built-in script changes with the version of the crate
+    let script = match &options.script {
+        Some(script) => script.display().to_string(),
+        None => format!("cmake-package {}", env!("CARGO_PKG_VERSION")),
+    };
+    let key = cache::key(
+        std::iter::once(script.into()).chain(
+            std::iter::once(command.get_program())
+                .chain(command.get_args())
+                .map(|arg| arg.to_string_lossy()),
+        ),
+        tracked_env_vars(package),
+    );
+    if let Some(result) = cache.as_ref().and_then(|cache| cache.get(&key)) {
+        return Ok(result);
     }
+
+    command.arg(format!("-DOUTPUT_FILE={}", output_file.display()));
+    run(command, options.verbose).map_err(compiler_not_found)?;
+
+    // Read from the generated JSON file
+    let result = read_output(output_file)?;
+    if let (Some(cache), Some(files)) = (cache, files(&result)) {
+        // Upgrading cmake or modifying the custom script or the toolchain file may change the
+        // result as well. The generated toolchain file is relative and derived from the environment.
+        let program = PathBuf::from(command.get_program());
+        let toolchain_file = options
+            .toolchain_file
+            .clone()
+            .filter(|file| file.is_absolute());
+        cache.put(
+            &key,
+            files
+                .into_iter()
+                .chain([program])
+                .chain(options.script.clone())
+                .chain(toolchain_file),
+            &result,
+        );
+    }
+    Ok(result)
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
-enum CMakeBuildType {
+/// Environment variable that overrides the build type of all packages (see [`CMakeBuildType`]).
+const BUILD_TYPE_ENV: &str = "CMAKE_PACKAGE_BUILD_TYPE";
+
+/// The CMake build type (configuration) used to select the libraries of the packages.
+///
+/// By default, the build type is derived from the Cargo profile: `Debug` for debug builds,
+/// `Release` for release builds, `MinSizeRel` for release builds optimized for size and
+/// `RelWithDebInfo` for release builds with debug info. It can be changed for a package with
+/// [`FindPackageBuilder::build_type()`][crate::FindPackageBuilder::build_type], and for all
+/// packages with the `CMAKE_PACKAGE_BUILD_TYPE` environment variable, which takes precedence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CMakeBuildType {
+    /// The `Debug` configuration
     Debug,
+    /// The `Release` configuration
     Release,
+    /// The `RelWithDebInfo` configuration
     RelWithDebInfo,
+    /// The `MinSizeRel` configuration
     MinSizeRel,
+    /// A custom configuration defined by the package (e.g. `Profile` or `RelWithAssert`)
+    Custom(String),
+}
+
+impl std::fmt::Display for CMakeBuildType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CMakeBuildType::Debug => write!(f, "Debug"),
+            CMakeBuildType::Release => write!(f, "Release"),
+            CMakeBuildType::RelWithDebInfo => write!(f, "RelWithDebInfo"),
+            CMakeBuildType::MinSizeRel => write!(f, "MinSizeRel"),
+            CMakeBuildType::Custom(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+impl From<&str> for CMakeBuildType {
+    /// Converts the name of a configuration into a build type. The standard CMake configurations
+    /// are matched case-insensitively, any other name is a [`CMakeBuildType::Custom`] configuration.
+    fn from(name: &str) -> Self {
+        [
+            CMakeBuildType::Debug,
+            CMakeBuildType::Release,
+            CMakeBuildType::RelWithDebInfo,
+            CMakeBuildType::MinSizeRel,
+        ]
+        .into_iter()
+        .find(|build_type| build_type.to_string().eq_ignore_ascii_case(name))
+        .unwrap_or_else(|| CMakeBuildType::Custom(name.to_string()))
+    }
+}
+
+impl std::str::FromStr for CMakeBuildType {
+    type Err = std::convert::Infallible;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Ok(CMakeBuildType::from(name))
+    }
+}
+
+/// Resolves the build type to use for a package. The `CMAKE_PACKAGE_BUILD_TYPE` environment
+/// variable takes precedence over the build type requested by the user, which takes precedence
+/// over the build type derived from the Cargo profile.
+fn resolve_build_type(requested: Option<CMakeBuildType>) -> CMakeBuildType {
+    std::env::var(BUILD_TYPE_ENV)
+        .ok()
+        .filter(|name| !name.is_empty())
+        .map(|name| CMakeBuildType::from(name.as_str()))
+        .or(requested)
+        .unwrap_or_else(build_type)
+}
+
+/// Returns names of all environment variables that affect the result of finding the package.
+pub(crate) fn tracked_env_vars(package: &str) -> Vec<String> {
+    let mut vars = vec!["CMAKE_PREFIX_PATH".to_string(), format!("{package}_ROOT")];
+    // CMake also checks the upper-case variant of <PackageName>_ROOT since 3.27
+    if package.to_uppercase() != package {
+        vars.push(format!("{}_ROOT", package.to_uppercase()));
+    }
+    vars.push(format!("{package}_DIR"));
+    vars.extend(
+        [
+            "PATH",
+            "PROFILE",
+            "OPT_LEVEL",
+            "DEBUG",
+            BUILD_TYPE_ENV,
+            RECORD_ENV,
+            REPLAY_ENV,
+        ]
+        .iter()
+        .map(ToString::to_string),
+    );
+    vars.extend(compiler::env_vars());
+    vars.extend(toolchain::env_vars());
+    vars
 }
 
+/// Derives the build type from the Cargo profile.
 fn build_type() -> CMakeBuildType {
     // The PROFILE variable is set to "release" for release builds and to "debug" for any other build type.
     // This is fairly easy to map to CMake's build types...
@@ -166,38 +651,52 @@ pub(crate) fn find_package(
     name: String,
     version: Option<Version>,
     components: Option<Vec<String>>,
-    verbose: bool,
+    mut options: CMakeOptions,
 ) -> Result<CMakePackage, Error> {
     // Find cmake or panic
-    let cmake = find_cmake()?;
+    let cmake = find_cmake_cached(options.cache().as_ref())?;
+
+    // Resolve the build type only once, so that all targets of the package use the same one
+    options.build_type = Some(resolve_build_type(options.build_type.take()));
 
     let working_directory = get_temporary_working_directory()?;
 
-    setup_cmake_project(working_directory.path())?;
+    setup_cmake_project(working_directory.path(), options.script.as_deref())?;
+    options.toolchain_file =
+        toolchain::setup(working_directory.path(), options.toolchain_file.take())?;
 
     let output_file = working_directory.path().join("package.json");
     // Run the CMake - see the find_package.cmake script for docs
     let mut command = Command::new(&cmake.path);
     command
-        .stdout(stdio(verbose))
-        .stderr(stdio(verbose))
         .current_dir(&working_directory)
         .arg(".")
-        .arg(format!("-DCMAKE_BUILD_TYPE={:?}", build_type()))
+        .arg(format!("-DCMAKE_BUILD_TYPE={}", options.build_type()))
         .arg(format!("-DCMAKE_MIN_VERSION={CMAKE_MIN_VERSION}"))
-        .arg(format!("-DPACKAGE={}", name))
-        .arg(format!("-DOUTPUT_FILE={}", output_file.display()));
+        .arg(format!("-DPACKAGE={}", name));
     if let Some(version) = version {
         command.arg(format!("-DVERSION={}", version));
     }
-    if let Some(components) = components {
+    if let Some(components) = &components {
         command.arg(format!("-DCOMPONENTS={}", components.join(";")));
     }
-    command.output().map_err(Error::IO)?;
-
-    // Read from the generated JSON file
-    let reader = std::fs::File::open(output_file).map_err(Error::IO)?;
-    let package: PackageResult = serde_json::from_reader(reader).or(Err(Error::Internal))?;
+    options.apply(&name, &mut command);
+    command.args(compiler::cmake_args());
+    let package: PackageResult = run_cached(
+        &mut command,
+        &output_file,
+        &name,
+        &options,
+        |package: &PackageResult| {
+            // Don't cache packages that were not found, they may get installed in the meantime.
+            // Packages found by a find module have no config files to track, so their result
+            // is not cached either.
+            package.name.as_ref()?;
+            let config_paths = package.config_paths();
+            (!config_paths.is_empty()).then_some(config_paths)
+        },
+    )?;
+    let config_paths = package.config_paths();
 
     let package_name = match package.name {
         Some(name) => name,
@@ -219,72 +718,67 @@ pub(crate) fn find_package(
         // It's not an error if the package did not provide a version.
     }
 
-    Ok(CMakePackage::new(
-        cmake,
-        working_directory,
+    let package = CMakePackage::new(
+        Source::CMake(Project {
+            cmake,
+            working_directory,
+        }),
         package_name,
         package_version,
         package.components,
-        verbose,
-    ))
+        package.targets.unwrap_or_default(),
+        config_paths,
+        options,
+    );
+    package.rerun_if_changed_write(&mut std::io::stdout());
+    Ok(package)
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(untagged)]
 enum PropertyValue {
     String(String),
-    Target(Target),
-}
-
-impl From<PropertyValue> for Vec<String> {
-    fn from(value: PropertyValue) -> Self {
-        match value {
-            PropertyValue::String(value) => vec![value],
-            PropertyValue::Target(target) => match target.location {
-                Some(location) => vec![location],
-                None => vec![],
-            }
-            .into_iter()
-            .chain(
-                target
-                    .interface_link_libraries
-                    .unwrap_or_default()
-                    .into_iter()
-                    .flat_map(Into::<Vec<String>>::into),
-            )
-            .collect(),
-        }
-    }
+    Target(Box<Target>),
 }
 
-#[derive(Debug, Default, Deserialize, Clone)]
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
 #[serde(default, rename_all = "UPPERCASE")]
 struct Target {
     name: String,
+    #[serde(rename = "TYPE")]
+    target_type: TargetType,
+    /// Location of the target as computed by CMake for the default configuration.
     location: Option<String>,
-    #[serde(rename = "LOCATION_Release")]
-    location_release: Option<String>,
-    #[serde(rename = "LOCATION_Debug")]
-    location_debug: Option<String>,
-    #[serde(rename = "LOCATION_RelWithDebInfo")]
-    location_relwithdebinfo: Option<String>,
-    #[serde(rename = "LOCATION_MinSizeRel")]
-    location_minsizerel: Option<String>,
+    /// Configuration-less location of the target.
+    imported_location: Option<String>,
+    /// Configuration-less import library of the target.
     imported_implib: Option<String>,
-    #[serde(rename = "IMPORTED_IMPLIB_Release")]
-    imported_implib_release: Option<String>,
-    #[serde(rename = "IMPORTED_IMPLIB_Debug")]
-    imported_implib_debug: Option<String>,
-    #[serde(rename = "IMPORTED_IMPLIB_RelWithDebInfo")]
-    imported_implib_relwithdebinfo: Option<String>,
-    #[serde(rename = "IMPORTED_IMPLIB_MinSizeRel")]
-    imported_implib_minsizerel: Option<String>,
+    /// Configurations provided by the target, in the order of preference.
+    imported_configurations: Vec<String>,
+    /// `IMPORTED_LOCATION_<CONFIG>` values, keyed by the upper-case configuration name.
+    imported_locations: HashMap<String, String>,
+    /// `IMPORTED_IMPLIB_<CONFIG>` values, keyed by the upper-case configuration name.
+    imported_implibs: HashMap<String, String>,
+    /// Value of `MAP_IMPORTED_CONFIG_<CONFIG>` for the current build type.
+    map_imported_config: Option<Vec<String>>,
     interface_compile_definitions: Option<Vec<String>>,
     interface_compile_options: Option<Vec<String>>,
     interface_include_directories: Option<Vec<String>>,
     interface_link_directories: Option<Vec<String>>,
     interface_link_libraries: Option<Vec<PropertyValue>>,
     interface_link_options: Option<Vec<String>>,
+    /// Targets referenced by generator expressions in `interface_link_libraries`, keyed by name.
+    genex_targets: HashMap<String, Target>,
+    /// Whether the target was linked via `$<LINK_ONLY:...>`, in which case it only contributes
+    /// to linking, not to the compilation of the consumer.
+    #[serde(skip)]
+    link_only: bool,
+    /// Features the target was linked with via `$<LINK_LIBRARY:...>` and `$<LINK_GROUP:...>`.
+    #[serde(skip)]
+    link_features: genex::LinkFeatures,
+    /// Warnings about the values that could not be evaluated, see [`Target::evaluate()`].
+    #[serde(skip)]
+    warnings: Vec<String>,
 }
 
 /// Collects values from `property` of the current target and from `property` of
@@ -306,6 +800,7 @@ struct Target {
 fn collect_from_targets<'a>(
     target: &'a Target,
     property: impl Fn(&Target) -> &Option<Vec<String>> + 'a + Copy,
+    linking: bool,
 ) -> Vec<String> {
     property(target)
         .as_ref()
@@ -319,9 +814,11 @@ fn collect_from_targets<'a>(
                 .iter()
                 .filter_map(|value| match value {
                     PropertyValue::String(_) => None,
+                    // Link-only dependencies don't propagate their compile requirements
+                    PropertyValue::Target(target) if target.link_only && !linking => None,
                     PropertyValue::Target(target) => Some(target),
                 })
-                .flat_map(|target| collect_from_targets(target, property)),
+                .flat_map(|target| collect_from_targets(target, property, linking)),
         )
         .collect()
 }
@@ -331,128 +828,514 @@ fn collect_from_targets<'a>(
 fn collect_from_targets_unique<'a>(
     target: &'a Target,
     property: impl Fn(&Target) -> &Option<Vec<String>> + 'a + Copy,
+    linking: bool,
 ) -> Vec<String> {
-    collect_from_targets(target, property)
+    collect_from_targets(target, property, linking)
         .into_iter()
         .sorted()
         .dedup()
         .collect()
 }
 
-fn implib_for_build_type(build_type: CMakeBuildType, target: &Target) -> Option<String> {
-    match build_type {
-        CMakeBuildType::Debug => target.imported_implib_debug.clone().or(target.imported_implib.clone()),
-        CMakeBuildType::Release => target.imported_implib_release.clone().or(target.imported_implib.clone()),
-        CMakeBuildType::RelWithDebInfo => target
-            .imported_implib_relwithdebinfo
-            .clone()
-            .or(target.imported_implib.clone()),
-        CMakeBuildType::MinSizeRel => target
-            .imported_implib_minsizerel
-            .clone()
-            .or(target.imported_implib.clone()),
-    }.or_else(|| location_for_build_type(build_type, target))
+/// Selects the configuration of an imported target for the build type, the same way CMake does
+/// (see [`MAP_IMPORTED_CONFIG_<CONFIG>`][cmake_map_imported_config]). `Some(None)` selects the
+/// configuration-less properties.
+///
+/// 1. If `MAP_IMPORTED_CONFIG_<CONFIG>` is set for the build type, the first of the mapped
+///    configurations provided by the target is used, an empty element selects the
+///    configuration-less properties. If the target provides none of them, nothing is selected.
+/// 2. Otherwise the configuration matching the build type is used, if the target provides it.
+/// 3. Otherwise the configuration-less properties are used, if the target has any.
+/// 4. Otherwise the first configuration from `IMPORTED_CONFIGURATIONS` that the target provides
+///    is used.
+///
+/// The target provides a configuration if it has its `IMPORTED_LOCATION_<CONFIG>` or
+/// `IMPORTED_IMPLIB_<CONFIG>`. Both the location and the import library are then taken from
+/// the selected configuration, so that they always match.
+///
+/// [cmake_map_imported_config]: https://cmake.org/cmake/help/latest/prop_tgt/MAP_IMPORTED_CONFIG_CONFIG.html
+fn config_for_build_type(build_type: &CMakeBuildType, target: &Target) -> Option<Option<String>> {
+    let provides = |config: &Option<String>| match config {
+        Some(config) => {
+            let config = config.to_uppercase();
+            target.imported_locations.contains_key(&config)
+                || target.imported_implibs.contains_key(&config)
+        }
+        None => target.imported_location.is_some() || target.imported_implib.is_some(),
+    };
+
+    if let Some(mapped_configs) = &target.map_imported_config {
+        return mapped_configs
+            .iter()
+            .map(|config| (!config.is_empty()).then(|| config.clone()))
+            .find(provides);
+    }
+
+    [Some(build_type.to_string()), None]
+        .into_iter()
+        .chain(target.imported_configurations.iter().cloned().map(Some))
+        .find(provides)
 }
 
-fn location_for_build_type(build_type: CMakeBuildType, target: &Target) -> Option<String> {
-    match build_type {
-        CMakeBuildType::Debug => target.location_debug.clone().or(target.location.clone()),
-        CMakeBuildType::Release => target.location_release.clone().or(target.location.clone()),
-        CMakeBuildType::RelWithDebInfo => target
-            .location_relwithdebinfo
-            .clone()
-            .or(target.location.clone()),
-        CMakeBuildType::MinSizeRel => target
-            .location_minsizerel
-            .clone()
-            .or(target.location.clone()),
+/// Returns the value of a per-configuration property for the `config` selected by
+/// [`config_for_build_type()`].
+fn select_for_config(
+    config: &Option<String>,
+    per_config: &HashMap<String, String>,
+    config_less: &Option<String>,
+) -> Option<String> {
+    match config {
+        Some(config) => per_config.get(&config.to_uppercase()).cloned(),
+        None => config_less.clone(),
+    }
+}
+
+fn implib_for_build_type(build_type: &CMakeBuildType, target: &Target) -> Option<String> {
+    config_for_build_type(build_type, target)
+        .and_then(|config| {
+            select_for_config(&config, &target.imported_implibs, &target.imported_implib)
+        })
+        .or_else(|| location_for_build_type(build_type, target))
+}
+
+fn location_for_build_type(build_type: &CMakeBuildType, target: &Target) -> Option<String> {
+    match config_for_build_type(build_type, target) {
+        Some(config) => select_for_config(
+            &config,
+            &target.imported_locations,
+            &target.imported_location,
+        ),
+        // LOCATION is computed by CMake from the same properties, but with no mapping applied
+        None => target
+            .map_imported_config
+            .is_none()
+            .then(|| target.location.clone())
+            .flatten(),
     }
 }
 
-fn library_for_build_type(build_type: CMakeBuildType, target: &Target) -> Option<String> {
-    if cfg!(target_os = "windows") {
+fn library_for_build_type(
+    build_type: &CMakeBuildType,
+    target: &Target,
+    platform: &TargetPlatform,
+) -> Option<String> {
+    if platform.is_windows() {
         implib_for_build_type(build_type, target)
     } else {
         location_for_build_type(build_type, target)
     }
 }
 
+/// Collects the libraries to link against for the target and all its dependencies.
+///
+/// The libraries are ordered so that every library comes before all the libraries it depends
+/// on, which is the order in which linkers resolve symbols from static libraries. When a library
+/// is reachable through multiple paths in the dependency graph (e.g. a diamond dependency), only
+/// its last occurrence in the depth-first traversal is kept, which keeps it after all its dependents.
+/// Linker flags are never deduplicated, since their position matters (e.g. `-Wl,--as-needed`).
+fn collect_link_libraries(
+    target: &Target,
+    build_type: &CMakeBuildType,
+    preference: LinkPreference,
+) -> Vec<LinkLibrary> {
+    fn visit(
+        target: &Target,
+        build_type: &CMakeBuildType,
+        platform: &TargetPlatform,
+        libraries: &mut Vec<LinkLibrary>,
+    ) {
+        // Interface and object libraries have no library file of their own to link against
+        if !matches!(
+            target.target_type,
+            TargetType::InterfaceLibrary | TargetType::ObjectLibrary
+        ) {
+            libraries.extend(
+                library_for_build_type(build_type, target, platform).map(|item| LinkLibrary {
+                    kind: LibraryKind::from_target_type(target.target_type, &item),
+                    target: Some(target.name.clone()),
+                    target_type: Some(target.target_type),
+                    feature: target.link_features.feature.clone(),
+                    group: target.link_features.group.clone(),
+                    item,
+                }),
+            );
+        }
+        for value in target.interface_link_libraries.iter().flatten() {
+            match value {
+                PropertyValue::String(value) => {
+                    let (item, features) = genex::strip_link_features(value);
+                    libraries.push(LinkLibrary {
+                        feature: features.feature,
+                        group: features.group,
+                        ..LinkLibrary::new(item)
+                    });
+                }
+                PropertyValue::Target(target) => visit(target, build_type, platform, libraries),
+            }
+        }
+    }
+
+    let mut libraries = Vec::new();
+    visit(
+        target,
+        build_type,
+        &TargetPlatform::from_env(),
+        &mut libraries,
+    );
+
+    // Only library files and targets are deduplicated, flags (including the framework name after
+    // a bare `-framework`) stay where they are, like CMake does
+    let flags = libraries
+        .iter()
+        .scan(false, |framework_arg, library| {
+            let item = library.item.trim();
+            let flag = *framework_arg || item.starts_with('-');
+            *framework_arg = item == "-framework";
+            Some(flag)
+        })
+        .collect::<Vec<_>>();
+    let mut seen = HashSet::new();
+    let mut libraries = libraries
+        .into_iter()
+        .zip(flags)
+        .rev()
+        .map(|(library, flag)| (preference.apply(library), flag))
+        .filter(|(library, flag)| *flag || seen.insert(library.item.clone()))
+        .map(|(library, _)| library)
+        .collect::<Vec<_>>();
+    libraries.reverse();
+    libraries
+}
+
 impl Target {
-    fn into_cmake_target(self, build_type: CMakeBuildType) -> CMakeTarget {
-        CMakeTarget {
-            compile_definitions: collect_from_targets_unique(&self, |target| {
-                &target.interface_compile_definitions
-            }),
-            compile_options: collect_from_targets(&self, |target| {
-                &target.interface_compile_options
-            }),
-            include_directories: collect_from_targets_unique(&self, |target| {
-                &target.interface_include_directories
-            }),
-            link_directories: collect_from_targets_unique(&self, |target| {
-                &target.interface_link_directories
-            }),
-            link_options: collect_from_targets(&self, |target| &target.interface_link_options),
-            link_libraries: library_for_build_type(build_type, &self)
-                .as_ref()
-                .map_or(vec![], |location| vec![location.clone()])
-                .into_iter()
-                .chain(
-                    self.interface_link_libraries
-                        .as_ref()
-                        .map_or(Vec::new(), Clone::clone)
-                        .into_iter()
-                        .flat_map(Into::<Vec<String>>::into),
-                )
-                .sorted() // FIXME: should we really do this for libraries? Linking order might be important...
-                .dedup()
+    /// Evaluates generator expressions in all properties of the target and its dependencies.
+    ///
+    /// Libraries that evaluate to a name of a target referenced by the expressions are replaced
+    /// by the target itself. Values that can't be evaluated are recorded in the `warnings`, see
+    /// [`Target::warnings_write()`].
+    fn evaluate(self, context: &genex::Context) -> Target {
+        let context = &genex::Context {
+            targets: self
+                .genex_targets
+                .keys()
+                .chain([&self.name])
+                .cloned()
                 .collect(),
+            ..context.clone()
+        };
+        let name = &self.name;
+        let mut warnings = Vec::new();
+        let report = |warnings: &mut Vec<String>, property: &str, unsupported: Vec<String>| {
+            warnings.extend(unsupported.into_iter().unique().map(|expression| {
+                format!("Target {name}: ignoring {expression} in {property}, which can't be evaluated outside of CMake")
+            }));
+        };
+        // The usage requirements are applied to the compilers of all the languages at once, so
+        // values that depend on the compiled language can't be used
+        let evaluate = |warnings: &mut Vec<String>, property: &str, values: Option<Vec<String>>| {
+            let mut unsupported = Vec::new();
+            let values = values.map(|values| {
+                values
+                    .iter()
+                    .flat_map(|value| {
+                        genex::evaluate_compile_list(value, context, &mut unsupported)
+                            .unwrap_or_else(|| {
+                                warnings.push(format!(
+                                    "Target {name}: ignoring {value} in {property}, which depends on the compiled language"
+                                ));
+                                Vec::new()
+                            })
+                    })
+                    .collect()
+            });
+            report(warnings, property, unsupported);
+            values
+        };
+
+        let mut unsupported = Vec::new();
+        let genex_targets = &self.genex_targets;
+        let interface_link_libraries = self.interface_link_libraries.map(|values| {
+            values
+                .into_iter()
+                .flat_map(|value| match value {
+                    PropertyValue::Target(target) => {
+                        vec![PropertyValue::Target(Box::new(target.evaluate(context)))]
+                    }
+                    PropertyValue::String(value) if genex::is_expression(&value) => {
+                        // Anything that disappears when evaluated for compilation is link-only
+                        let usage = genex::evaluate_list(&value, context, false, &mut unsupported);
+                        genex::evaluate_list(&value, context, true, &mut unsupported)
+                            .into_iter()
+                            .map(|item| {
+                                // Libraries with link features keep them until they are collected
+                                let (library, features) = genex::strip_link_features(&item);
+                                match genex_targets.get(library) {
+                                    Some(target) => {
+                                        let mut target = target.clone().evaluate(context);
+                                        target.link_only = !usage.iter().any(|u| u == library);
+                                        target.link_features = features;
+                                        PropertyValue::Target(Box::new(target))
+                                    }
+                                    None => PropertyValue::String(item),
+                                }
+                            })
+                            .collect()
+                    }
+                    value => vec![value],
+                })
+                .collect()
+        });
+        report(&mut warnings, "INTERFACE_LINK_LIBRARIES", unsupported);
+
+        let mut unsupported = Vec::new();
+        let interface_link_options = self.interface_link_options.map(|values| {
+            options::expand_link_options(
+                values
+                    .iter()
+                    .flat_map(|value| genex::evaluate_list(value, context, true, &mut unsupported))
+                    .collect::<Vec<_>>(),
+                context,
+            )
+        });
+        report(&mut warnings, "INTERFACE_LINK_OPTIONS", unsupported);
+
+        Target {
+            interface_compile_definitions: evaluate(
+                &mut warnings,
+                "INTERFACE_COMPILE_DEFINITIONS",
+                self.interface_compile_definitions,
+            ),
+            interface_compile_options: evaluate(
+                &mut warnings,
+                "INTERFACE_COMPILE_OPTIONS",
+                self.interface_compile_options,
+            )
+            .map(options::expand_compile_options),
+            interface_include_directories: evaluate(
+                &mut warnings,
+                "INTERFACE_INCLUDE_DIRECTORIES",
+                self.interface_include_directories,
+            ),
+            interface_link_directories: evaluate(
+                &mut warnings,
+                "INTERFACE_LINK_DIRECTORIES",
+                self.interface_link_directories,
+            ),
+            interface_link_libraries,
+            interface_link_options,
+            genex_targets: HashMap::new(),
+            warnings,
+            ..self
+        }
+    }
+
+    /// Writes the warnings about the values of the target and its dependencies that could not
+    /// be evaluated as `cargo:warning` directives.
+    fn warnings_write<W: Write>(&self, io: &mut W) {
+        fn collect<'a>(target: &'a Target, warnings: &mut Vec<&'a str>) {
+            warnings.extend(target.warnings.iter().map(String::as_str));
+            for value in target.interface_link_libraries.iter().flatten() {
+                if let PropertyValue::Target(target) = value {
+                    collect(target, warnings);
+                }
+            }
+        }
+
+        let mut warnings = Vec::new();
+        collect(self, &mut warnings);
+        for warning in warnings.into_iter().unique() {
+            writeln!(io, "cargo:warning={}", warning).unwrap();
+        }
+    }
+
+    /// Collects paths to all library files of the target and its dependencies.
+    fn collect_files(&self, files: &mut Vec<PathBuf>) {
+        files.extend(
+            [
+                &self.location,
+                &self.imported_location,
+                &self.imported_implib,
+            ]
+            .into_iter()
+            .flatten()
+            .chain(self.imported_locations.values())
+            .chain(self.imported_implibs.values())
+            .map(PathBuf::from),
+        );
+        self.interface_link_libraries
+            .iter()
+            .flatten()
+            .filter_map(|value| match value {
+                PropertyValue::Target(target) => Some(target.as_ref()),
+                PropertyValue::String(_) => None,
+            })
+            .chain(self.genex_targets.values())
+            .for_each(|target| target.collect_files(files));
+    }
+
+    fn into_cmake_executable(self, build_type: &CMakeBuildType) -> Result<CMakeExecutable, Error> {
+        if self.target_type != TargetType::Executable {
+            return Err(Error::NotAnExecutable {
+                target: self.name,
+                target_type: self.target_type,
+            });
+        }
+        match location_for_build_type(build_type, &self) {
+            Some(location) => Ok(CMakeExecutable {
+                name: self.name,
+                location: location.into(),
+            }),
+            None => Err(Error::LocationNotFound { target: self.name }),
+        }
+    }
+
+    fn into_cmake_target(
+        self,
+        build_type: &CMakeBuildType,
+        preference: LinkPreference,
+    ) -> CMakeTarget {
+        CMakeTarget {
+            compile_definitions: collect_from_targets_unique(
+                &self,
+                |target| &target.interface_compile_definitions,
+                false,
+            ),
+            compile_options: collect_from_targets(
+                &self,
+                |target| &target.interface_compile_options,
+                false,
+            ),
+            include_directories: collect_from_targets_unique(
+                &self,
+                |target| &target.interface_include_directories,
+                false,
+            ),
+            link_directories: collect_from_targets_unique(
+                &self,
+                |target| &target.interface_link_directories,
+                true,
+            ),
+            link_options: collect_from_targets(
+                &self,
+                |target| &target.interface_link_options,
+                true,
+            ),
+            link_libraries: collect_link_libraries(&self, build_type, preference),
+            target_type: self.target_type,
             name: self.name,
         }
     }
 }
 
-/// Finds the specified target in the CMake package and extracts its properties.
-/// Returns `None` if the target was not found.
-pub(crate) fn find_target(
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+struct TargetsResult {
+    #[serde(default)]
+    targets: HashMap<String, Target>,
+    #[serde(default)]
+    not_found: Vec<String>,
+    #[serde(default)]
+    context: genex::Context,
+}
+
+/// Finds the specified targets in the CMake package and extracts their properties.
+///
+/// All targets are resolved in a single CMake run. The returned vector has the same length and
+/// order as `targets`, with `None` for each target that CMake reported as not found.
+pub(crate) fn find_targets(
+    package: &CMakePackage,
+    project: &Project,
+    targets: &[impl AsRef<str>],
+) -> Result<Vec<Option<CMakeTarget>>, Error> {
+    let targets: Vec<&str> = targets.iter().map(AsRef::as_ref).collect();
+
+    let (output_file, mut result) = resolve_targets(package, project, &targets)?;
+    let build_type = package.options.build_type();
+    let context = genex::Context {
+        config: build_type.to_string(),
+        ..result.context
+    };
+    targets
+        .iter()
+        .map(|target| match result.targets.remove(*target) {
+            Some(target) => {
+                let target = target.evaluate(&context);
+                target.warnings_write(&mut std::io::stdout());
+                Ok(Some(target.into_cmake_target(
+                    &build_type,
+                    package.options.link_preference,
+                )))
+            }
+            None if result.not_found.iter().any(|t| t == target) => Ok(None),
+            // The script must report every target either as resolved or as not found
+            None => Err(Error::InvalidOutput {
+                path: output_file.clone(),
+                source: serde::de::Error::custom(format!("target {target} missing in output")),
+            }),
+        })
+        .collect()
+}
+
+/// Resolves an imported executable target, returns `None` if the target does not exist in the package.
+pub(crate) fn find_executable(
+    package: &CMakePackage,
+    project: &Project,
+    name: &str,
+) -> Result<Option<CMakeExecutable>, Error> {
+    let (output_file, mut result) = resolve_targets(package, project, &[name])?;
+    match result.targets.remove(name) {
+        Some(target) => target
+            .into_cmake_executable(&package.options.build_type())
+            .map(Some),
+        None if result.not_found.iter().any(|t| t == name) => Ok(None),
+        None => Err(Error::InvalidOutput {
+            path: output_file,
+            source: serde::de::Error::custom(format!("target {name} missing in output")),
+        }),
+    }
+}
+
+fn resolve_targets(
     package: &CMakePackage,
-    target: impl Into<String>,
-) -> Option<CMakeTarget> {
-    let target: String = target.into();
-
-    // Run the CMake script
-    let output_file = package.working_directory.path().join(format!(
-        "target_{}.json",
-        target.to_lowercase().replace(":", "_")
-    ));
-    let build_type = build_type();
-    let mut command = Command::new(&package.cmake.path);
+    project: &Project,
+    targets: &[&str],
+) -> Result<(PathBuf, TargetsResult), Error> {
+    // Run the CMake script. The output is read right after each run, so the file name can be fixed
+    // rather than derived from the target names, which may exceed the file name length limit.
+    let output_file = project.working_directory.path().join("targets.json");
+    let mut command = Command::new(&project.cmake.path);
     command
-        .stdout(stdio(package.verbose))
-        .stderr(stdio(package.verbose))
-        .current_dir(package.working_directory.path())
+        .current_dir(project.working_directory.path())
         .arg(".")
-        .arg(format!("-DCMAKE_BUILD_TYPE={:?}", build_type))
+        .arg(format!(
+            "-DCMAKE_BUILD_TYPE={}",
+            package.options.build_type()
+        ))
         .arg(format!("-DCMAKE_MIN_VERSION={CMAKE_MIN_VERSION}"))
         .arg(format!("-DPACKAGE={}", package.name))
-        .arg(format!("-DTARGET={}", target))
-        .arg(format!("-DOUTPUT_FILE={}", output_file.display()));
+        .arg(format!("-DTARGETS={}", targets.join(";")));
     if let Some(version) = package.version {
         command.arg(format!("-DVERSION={}", version));
     }
     if let Some(components) = &package.components {
         command.arg(format!("-DCOMPONENTS={}", components.join(";")));
     }
-    command.output().ok()?;
-
-    // Read from the generated JSON file
-    let reader = std::fs::File::open(&output_file).ok()?;
-    let target: Target = serde_json::from_reader(reader)
-        .map_err(|e| {
-            eprintln!("Failed to parse target JSON: {:?}", e);
-        })
-        .ok()?;
-    Some(target.into_cmake_target(build_type))
+    package.options.apply(&package.name, &mut command);
+    command.args(compiler::cmake_args());
+    let result = run_cached(
+        &mut command,
+        &output_file,
+        &package.name,
+        &package.options,
+        |result: &TargetsResult| {
+            let mut files = package.config_paths.clone();
+            result
+                .targets
+                .values()
+                .for_each(|target| target.collect_files(&mut files));
+            Some(files)
+        },
+    )?;
+    Ok((output_file, result))
 }
 
 #[cfg(test)]
@@ -461,6 +1344,14 @@ mod testing {
     use serial_test::serial;
 
     use super::*;
+    use crate::link::{LinkItem, LinkKind};
+
+    fn link_items(libraries: &[LinkLibrary]) -> Vec<&str> {
+        libraries
+            .iter()
+            .map(|library| library.item.as_str())
+            .collect()
+    }
 
     #[test]
     fn from_target() {
@@ -475,7 +1366,7 @@ mod testing {
             interface_link_libraries: Some(vec![
                 PropertyValue::String("library1".to_string()),
                 PropertyValue::String("library2".to_string()),
-                PropertyValue::Target(Target {
+                PropertyValue::Target(Box::new(Target {
                     name: "dependency".to_string(),
                     location: Some("/path/to/dependency.so".to_string()),
                     interface_compile_definitions: Some(vec!["DEFINE3".to_string()]),
@@ -489,12 +1380,13 @@ mod testing {
                         "dependency_library".to_string(),
                     )]),
                     ..Default::default()
-                }),
+                })),
             ]),
             ..Default::default()
         };
 
-        let cmake_target: CMakeTarget = target.into_cmake_target(CMakeBuildType::Release);
+        let cmake_target: CMakeTarget =
+            target.into_cmake_target(&CMakeBuildType::Release, LinkPreference::Default);
 
         assert_eq!(cmake_target.name, "my_target");
         assert_eq!(
@@ -515,45 +1407,471 @@ mod testing {
             vec!["-L/path/to/lib", "-L/path/to/dependency/lib"]
         );
         assert_eq!(
-            cmake_target.link_libraries,
+            link_items(&cmake_target.link_libraries),
             vec![
-                "/path/to/dependency.so",
                 "/path/to/target.so",
-                "dependency_library",
                 "library1",
                 "library2",
+                "/path/to/dependency.so",
+                "dependency_library",
             ]
         );
     }
 
-    #[test]
-    fn from_debug_target() {
-        let target = Target {
-            name: "test_target".to_string(),
-            location: Some("/path/to/target.so".to_string()),
-            location_debug: Some("/path/to/libtarget_debug.so".to_string()),
+    fn library(name: &str, dependencies: Vec<Target>) -> Target {
+        Target {
+            name: name.to_string(),
+            location: Some(format!("/usr/lib/lib{name}.a")),
+            interface_link_libraries: Some(
+                dependencies
+                    .into_iter()
+                    .map(|dependency| PropertyValue::Target(Box::new(dependency)))
+                    .collect(),
+            ),
             ..Default::default()
+        }
+    }
+
+    #[test]
+    fn link_order_diamond() {
+        // app -> left -> base, app -> right -> base
+        let base = library("base", vec![]);
+        let left = library("left", vec![base.clone()]);
+        let right = library("right", vec![base]);
+        let app = library("app", vec![left, right]);
+
+        assert_eq!(
+            link_items(
+                &app.into_cmake_target(&CMakeBuildType::Release, LinkPreference::Default)
+                    .link_libraries
+            ),
+            vec![
+                "/usr/lib/libapp.a",
+                "/usr/lib/libleft.a",
+                "/usr/lib/libright.a",
+                "/usr/lib/libbase.a",
+            ]
+        );
+    }
+
+    #[test]
+    fn link_order_nested_diamond() {
+        // top depends on both `mid` and `base` directly, `mid` depends on `left` and `right`,
+        // which both depend on `base`; `right` also depends on `left`.
+        let base = library("base", vec![]);
+        let left = library("left", vec![base.clone()]);
+        let right = library("right", vec![left.clone(), base.clone()]);
+        let mid = library("mid", vec![left, right]);
+        let mut top = library("top", vec![base, mid]);
+        top.interface_link_libraries
+            .as_mut()
+            .unwrap()
+            .push(PropertyValue::String("-lm".to_string()));
+
+        let libraries = top
+            .into_cmake_target(&CMakeBuildType::Release, LinkPreference::Default)
+            .link_libraries;
+        assert_eq!(
+            link_items(&libraries),
+            vec![
+                "/usr/lib/libtop.a",
+                "/usr/lib/libmid.a",
+                "/usr/lib/libright.a",
+                "/usr/lib/libleft.a",
+                "/usr/lib/libbase.a",
+                "-lm",
+            ]
+        );
+        // Every library must come before all of its dependencies
+        let position = |name: &str| {
+            libraries
+                .iter()
+                .position(|library| library.item == format!("/usr/lib/lib{name}.a"))
+                .unwrap()
+        };
+        for (dependent, dependency) in [
+            ("top", "base"),
+            ("top", "mid"),
+            ("mid", "left"),
+            ("mid", "right"),
+            ("right", "left"),
+            ("right", "base"),
+            ("left", "base"),
+        ] {
+            assert!(position(dependent) < position(dependency));
+        }
+    }
+
+    #[test]
+    fn link_flags_not_deduplicated() {
+        let flags = |items: &[&str]| {
+            items
+                .iter()
+                .map(|item| PropertyValue::String(item.to_string()))
+                .collect::<Vec<_>>()
         };
+        let mut base = library("base", vec![]);
+        base.interface_link_libraries = Some(flags(&[
+            "-framework",
+            "Cocoa",
+            "-framework",
+            "IOKit",
+            "-Wl,--as-needed",
+            "z",
+            "-Wl,--no-as-needed",
+        ]));
+        let mut app = library("app", vec![base]);
+        app.interface_link_libraries
+            .as_mut()
+            .unwrap()
+            .extend(flags(&["-Wl,--as-needed", "z", "-Wl,--no-as-needed"]));
 
-        let cmake_target = target.into_cmake_target(CMakeBuildType::Debug);
-        assert_eq!(cmake_target.link_libraries, vec!["/path/to/libtarget_debug.so"]);
+        let libraries =
+            collect_link_libraries(&app, &CMakeBuildType::Release, LinkPreference::Default);
+        assert_eq!(
+            link_items(&libraries),
+            vec![
+                "/usr/lib/libapp.a",
+                "/usr/lib/libbase.a",
+                "-framework",
+                "Cocoa",
+                "-framework",
+                "IOKit",
+                "-Wl,--as-needed",
+                "-Wl,--no-as-needed",
+                "-Wl,--as-needed",
+                "z",
+                "-Wl,--no-as-needed",
+            ]
+        );
+        let macos = TargetPlatform::new("macos", "", "apple");
+        assert!(macos.classify(&libraries)[2..4]
+            .iter()
+            .all(|(_, item)| matches!(item, LinkItem::Name(LinkKind::Framework, _))));
     }
 
     #[test]
-    fn from_json() {
+    fn from_static_target() {
         let json = r#"
 {
-  "INTERFACE_INCLUDE_DIRECTORIES" : [ "/usr/include" ],
+  "INTERFACE_LINK_LIBRARIES" : [ "m" ],
+  "LOCATION" : "/opt/foo/lib/foo.lib",
+  "NAME" : "Foo::Foo",
+  "TYPE" : "STATIC_LIBRARY"
+}
+"#;
+        let target: Target = serde_json::from_str(json).expect("Failed to parse JSON");
+        let cmake_target =
+            target.into_cmake_target(&CMakeBuildType::Release, LinkPreference::Default);
+        assert_eq!(
+            cmake_target.link_libraries,
+            vec![
+                LinkLibrary {
+                    item: "/opt/foo/lib/foo.lib".to_string(),
+                    target: Some("Foo::Foo".to_string()),
+                    target_type: Some(TargetType::StaticLibrary),
+                    kind: LibraryKind::Static,
+                    ..Default::default()
+                },
+                LinkLibrary {
+                    item: "m".to_string(),
+                    target: None,
+                    target_type: None,
+                    kind: LibraryKind::Unknown,
+                    ..Default::default()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn from_interface_target() {
+        let json = r#"
+{
+  "INTERFACE_INCLUDE_DIRECTORIES" : [ "/usr/include/boost" ],
   "INTERFACE_LINK_LIBRARIES" :
   [
     {
-      "INTERFACE_INCLUDE_DIRECTORIES" : [ "/usr/include" ],
-      "LOCATION" : "/usr/lib/libcrypto.so",
-      "NAME" : "OpenSSL::Crypto"
+      "LOCATION" : "/usr/lib/libboost_system.so.1.83.0",
+      "NAME" : "Boost::system",
+      "TYPE" : "SHARED_LIBRARY"
+    },
+    {
+      "NAME" : "Boost::disable_autolinking",
+      "TYPE" : "INTERFACE_LIBRARY"
     }
   ],
-  "LOCATION" : "/usr/lib/libssl.so",
-  "NAME" : "OpenSSL::SSL"
+  "LOCATION" : "/should/not/be/used.so",
+  "NAME" : "Boost::headers",
+  "TYPE" : "INTERFACE_LIBRARY"
+}
+"#;
+        let target: Target = serde_json::from_str(json).expect("Failed to parse JSON");
+        let cmake_target =
+            target.into_cmake_target(&CMakeBuildType::Release, LinkPreference::Default);
+        assert_eq!(cmake_target.target_type, TargetType::InterfaceLibrary);
+        assert_eq!(
+            cmake_target.link_libraries,
+            vec![LinkLibrary {
+                item: "/usr/lib/libboost_system.so.1.83.0".to_string(),
+                target: Some("Boost::system".to_string()),
+                target_type: Some(TargetType::SharedLibrary),
+                kind: LibraryKind::Dynamic,
+                ..Default::default()
+            }]
+        );
+    }
+
+    #[test]
+    fn target_types() {
+        for (name, target_type) in [
+            ("STATIC_LIBRARY", TargetType::StaticLibrary),
+            ("SHARED_LIBRARY", TargetType::SharedLibrary),
+            ("MODULE_LIBRARY", TargetType::ModuleLibrary),
+            ("UNKNOWN_LIBRARY", TargetType::UnknownLibrary),
+            ("INTERFACE_LIBRARY", TargetType::InterfaceLibrary),
+            ("OBJECT_LIBRARY", TargetType::ObjectLibrary),
+            ("EXECUTABLE", TargetType::Executable),
+            ("SOMETHING_NEW", TargetType::UnknownLibrary),
+        ] {
+            let target: Target = serde_json::from_str(&format!(r#"{{ "TYPE" : "{name}" }}"#))
+                .expect("Failed to parse JSON");
+            assert_eq!(target.target_type, target_type);
+        }
+        let target: Target = serde_json::from_str("{}").expect("Failed to parse JSON");
+        assert_eq!(target.target_type, TargetType::UnknownLibrary);
+    }
+
+    #[test]
+    fn from_executable_target() {
+        let json = r#"
+{
+  "IMPORTED_CONFIGURATIONS" : [ "RELEASE", "DEBUG" ],
+  "IMPORTED_LOCATIONS" :
+  {
+    "DEBUG" : "/usr/lib/qt6/libexec/moc_debug",
+    "RELEASE" : "/usr/lib/qt6/libexec/moc"
+  },
+  "NAME" : "Qt6::moc",
+  "TYPE" : "EXECUTABLE"
+}
+"#;
+        let target: Target = serde_json::from_str(json).expect("Failed to parse JSON");
+        let executable = target
+            .clone()
+            .into_cmake_executable(&CMakeBuildType::Release)
+            .expect("Failed to resolve executable");
+        assert_eq!(executable.name, "Qt6::moc");
+        assert_eq!(executable.location, Path::new("/usr/lib/qt6/libexec/moc"));
+        assert_eq!(
+            executable.command().get_program(),
+            "/usr/lib/qt6/libexec/moc"
+        );
+
+        let executable = target
+            .into_cmake_executable(&CMakeBuildType::Debug)
+            .expect("Failed to resolve executable");
+        assert_eq!(
+            executable.location,
+            Path::new("/usr/lib/qt6/libexec/moc_debug")
+        );
+
+        let target = Target {
+            name: "Qt6::Core".to_string(),
+            target_type: TargetType::SharedLibrary,
+            location: Some("/usr/lib/libQt6Core.so.6".to_string()),
+            ..Default::default()
+        };
+        match target.into_cmake_executable(&CMakeBuildType::Release) {
+            Err(Error::NotAnExecutable {
+                target,
+                target_type,
+            }) => {
+                assert_eq!(target, "Qt6::Core");
+                assert_eq!(target_type, TargetType::SharedLibrary);
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        let target = Target {
+            name: "Foo::tool".to_string(),
+            target_type: TargetType::Executable,
+            ..Default::default()
+        };
+        assert!(matches!(
+            target.into_cmake_executable(&CMakeBuildType::Release),
+            Err(Error::LocationNotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn from_debug_target() {
+        let target = Target {
+            name: "test_target".to_string(),
+            location: Some("/path/to/target.so".to_string()),
+            imported_configurations: vec!["DEBUG".to_string()],
+            imported_locations: HashMap::from([(
+                "DEBUG".to_string(),
+                "/path/to/libtarget_debug.so".to_string(),
+            )]),
+            ..Default::default()
+        };
+
+        let cmake_target =
+            target.into_cmake_target(&CMakeBuildType::Debug, LinkPreference::Default);
+        assert_eq!(
+            link_items(&cmake_target.link_libraries),
+            vec!["/path/to/libtarget_debug.so"]
+        );
+    }
+
+    #[test]
+    fn imported_configurations() {
+        let target =
+            |json: &str| -> Target { serde_json::from_str(json).expect("Failed to parse JSON") };
+        let location = |target: &Target, build_type| location_for_build_type(&build_type, target);
+
+        // Conan-style lowercase configuration names
+        let conan = target(
+            r#"{
+  "IMPORTED_CONFIGURATIONS" : [ "release" ],
+  "IMPORTED_LOCATIONS" : { "RELEASE" : "/conan/lib/libfoo.a" }
+}"#,
+        );
+        assert_eq!(
+            location(&conan, CMakeBuildType::Release).as_deref(),
+            Some("/conan/lib/libfoo.a")
+        );
+        // Falls back to any available configuration
+        assert_eq!(
+            location(&conan, CMakeBuildType::Debug).as_deref(),
+            Some("/conan/lib/libfoo.a")
+        );
+
+        // Custom configurations, the exact match wins, then the configuration-less location
+        let custom = target(
+            r#"{
+  "IMPORTED_CONFIGURATIONS" : [ "RELWITHASSERT", "DEBUG" ],
+  "IMPORTED_LOCATION" : "/usr/lib/libfoo.so",
+  "IMPORTED_LOCATIONS" :
+  {
+    "DEBUG" : "/usr/lib/libfoo_d.so",
+    "RELWITHASSERT" : "/usr/lib/libfoo_ra.so"
+  }
+}"#,
+        );
+        assert_eq!(
+            location(&custom, CMakeBuildType::Debug).as_deref(),
+            Some("/usr/lib/libfoo_d.so")
+        );
+        assert_eq!(
+            location(&custom, CMakeBuildType::Release).as_deref(),
+            Some("/usr/lib/libfoo.so")
+        );
+
+        // Mapped configurations are tried in order
+        let mapped = target(
+            r#"{
+  "IMPORTED_CONFIGURATIONS" : [ "RELWITHASSERT", "DEBUG" ],
+  "IMPORTED_LOCATIONS" :
+  {
+    "DEBUG" : "/usr/lib/libfoo_d.so",
+    "RELWITHASSERT" : "/usr/lib/libfoo_ra.so"
+  },
+  "MAP_IMPORTED_CONFIG" : [ "Profile", "RelWithAssert" ]
+}"#,
+        );
+        assert_eq!(
+            location(&mapped, CMakeBuildType::Release).as_deref(),
+            Some("/usr/lib/libfoo_ra.so")
+        );
+
+        // When none of the mapped configurations is available, there's no location at all
+        let unavailable = target(
+            r#"{
+  "IMPORTED_CONFIGURATIONS" : [ "DEBUG" ],
+  "IMPORTED_LOCATIONS" : { "DEBUG" : "/usr/lib/libfoo_d.so" },
+  "LOCATION" : "/usr/lib/libfoo_d.so",
+  "MAP_IMPORTED_CONFIG" : [ "Profile" ]
+}"#,
+        );
+        assert_eq!(location(&unavailable, CMakeBuildType::Release), None);
+
+        // Import libraries follow the same rules and fall back to the location
+        let implib = target(
+            r#"{
+  "IMPORTED_CONFIGURATIONS" : [ "RELEASE" ],
+  "IMPORTED_IMPLIBS" : { "RELEASE" : "C:/foo/lib/foo.lib" },
+  "IMPORTED_LOCATIONS" : { "RELEASE" : "C:/foo/bin/foo.dll" }
+}"#,
+        );
+        assert_eq!(
+            implib_for_build_type(&CMakeBuildType::Debug, &implib).as_deref(),
+            Some("C:/foo/lib/foo.lib")
+        );
+        let no_implib = target(r#"{ "IMPORTED_LOCATION" : "C:/foo/lib/foo.lib" }"#);
+        assert_eq!(
+            implib_for_build_type(&CMakeBuildType::Debug, &no_implib).as_deref(),
+            Some("C:/foo/lib/foo.lib")
+        );
+
+        // An empty mapped configuration selects the configuration-less properties
+        let empty_mapped = target(
+            r#"{
+  "IMPORTED_CONFIGURATIONS" : [ "RELEASE" ],
+  "IMPORTED_IMPLIB" : "C:/foo/lib/foo.lib",
+  "IMPORTED_IMPLIBS" : { "RELEASE" : "C:/foo/lib/foo-rel.lib" },
+  "IMPORTED_LOCATION" : "C:/foo/bin/foo.dll",
+  "IMPORTED_LOCATIONS" : { "RELEASE" : "C:/foo/bin/foo-rel.dll" },
+  "MAP_IMPORTED_CONFIG" : [ "Profile", "", "Release" ]
+}"#,
+        );
+        assert_eq!(
+            location(&empty_mapped, CMakeBuildType::Debug).as_deref(),
+            Some("C:/foo/bin/foo.dll")
+        );
+        assert_eq!(
+            implib_for_build_type(&CMakeBuildType::Debug, &empty_mapped).as_deref(),
+            Some("C:/foo/lib/foo.lib")
+        );
+
+        // The location and the import library come from the same configuration
+        let mixed = target(
+            r#"{
+  "IMPORTED_CONFIGURATIONS" : [ "DEBUG", "RELEASE" ],
+  "IMPORTED_IMPLIBS" : { "RELEASE" : "C:/foo/lib/foo.lib" },
+  "IMPORTED_LOCATIONS" :
+  {
+    "DEBUG" : "C:/foo/lib/foo_d.lib",
+    "RELEASE" : "C:/foo/bin/foo.dll"
+  }
+}"#,
+        );
+        assert_eq!(
+            location(&mixed, CMakeBuildType::Debug).as_deref(),
+            Some("C:/foo/lib/foo_d.lib")
+        );
+        assert_eq!(
+            implib_for_build_type(&CMakeBuildType::Debug, &mixed).as_deref(),
+            Some("C:/foo/lib/foo_d.lib")
+        );
+    }
+
+    #[test]
+    fn from_json() {
+        let json = r#"
+{
+  "INTERFACE_INCLUDE_DIRECTORIES" : [ "/usr/include" ],
+  "INTERFACE_LINK_LIBRARIES" :
+  [
+    {
+      "INTERFACE_INCLUDE_DIRECTORIES" : [ "/usr/include" ],
+      "LOCATION" : "/usr/lib/libcrypto.so",
+      "NAME" : "OpenSSL::Crypto"
+    }
+  ],
+  "LOCATION" : "/usr/lib/libssl.so",
+  "NAME" : "OpenSSL::SSL"
 }
 "#;
         let target: Target = serde_json::from_str(json).expect("Failed to parse JSON");
@@ -583,6 +1901,496 @@ mod testing {
         }
     }
 
+    #[test]
+    fn from_targets_json() {
+        let json = r#"
+{
+  "targets" :
+  {
+    "OpenSSL::Crypto" :
+    {
+      "LOCATION" : "/usr/lib/libcrypto.so",
+      "NAME" : "OpenSSL::Crypto"
+    },
+    "OpenSSL::SSL" :
+    {
+      "INTERFACE_LINK_LIBRARIES" :
+      [
+        {
+          "LOCATION" : "/usr/lib/libcrypto.so",
+          "NAME" : "OpenSSL::Crypto"
+        }
+      ],
+      "LOCATION" : "/usr/lib/libssl.so",
+      "NAME" : "OpenSSL::SSL"
+    }
+  }
+}
+"#;
+        let result: TargetsResult = serde_json::from_str(json).expect("Failed to parse JSON");
+        assert_eq!(result.targets.len(), 2);
+        assert!(result.not_found.is_empty());
+        assert_eq!(result.targets["OpenSSL::Crypto"].name, "OpenSSL::Crypto");
+        assert_eq!(
+            result.targets["OpenSSL::Crypto"].location,
+            Some("/usr/lib/libcrypto.so".to_string())
+        );
+        assert_eq!(result.targets["OpenSSL::SSL"].name, "OpenSSL::SSL");
+        assert_eq!(
+            result.targets["OpenSSL::SSL"]
+                .interface_link_libraries
+                .as_ref()
+                .map(Vec::len),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn from_genex_targets_json() {
+        let json = r#"
+{
+  "targets" :
+  {
+    "Foo::Foo" :
+    {
+      "GENEX_TARGETS" :
+      {
+        "Threads::Threads" :
+        {
+          "INTERFACE_COMPILE_OPTIONS" : [ "-pthread" ],
+          "INTERFACE_LINK_LIBRARIES" : [ "-lpthread" ],
+          "NAME" : "Threads::Threads"
+        }
+      },
+      "INTERFACE_COMPILE_DEFINITIONS" : [ "FOO", "$<$<CONFIG:Debug>:FOO_DEBUG>", "$<$<CONFIG:Release>:FOO_RELEASE>" ],
+      "INTERFACE_INCLUDE_DIRECTORIES" : [ "$<INSTALL_INTERFACE:include>", "/usr/include/foo" ],
+      "INTERFACE_LINK_LIBRARIES" : [ "$<LINK_ONLY:Threads::Threads>", "$<$<PLATFORM_ID:Windows>:ws2_32>" ],
+      "INTERFACE_LINK_OPTIONS" : [ "$<$<PLATFORM_ID:Linux>:LINKER:--no-undefined>" ],
+      "LOCATION" : "/usr/lib/libfoo.so",
+      "NAME" : "Foo::Foo"
+    }
+  },
+  "context" :
+  {
+    "PLATFORM_ID" : "Linux",
+    "C_COMPILER_ID" : "GNU",
+    "C_COMPILER_VERSION" : "14.2.1",
+    "LINKER_WRAPPER_FLAG" : "-Xlinker; ",
+    "LINKER_WRAPPER_FLAG_SEP" : ""
+  }
+}
+"#;
+        let mut result: TargetsResult = serde_json::from_str(json).expect("Failed to parse JSON");
+        assert_eq!(result.context.platform_id, "Linux");
+        assert_eq!(result.context.c_compiler_id, "GNU");
+
+        let context = genex::Context {
+            config: "Debug".to_string(),
+            ..result.context
+        };
+        let target = result
+            .targets
+            .remove("Foo::Foo")
+            .unwrap()
+            .evaluate(&context)
+            .into_cmake_target(&CMakeBuildType::Debug, LinkPreference::Default);
+        assert_eq!(target.compile_definitions, vec!["FOO", "FOO_DEBUG"]);
+        assert_eq!(target.include_directories, vec!["/usr/include/foo"]);
+        // Compile options of the link-only dependency are not propagated
+        assert!(target.compile_options.is_empty());
+        assert_eq!(
+            link_items(&target.link_libraries),
+            vec!["/usr/lib/libfoo.so", "-lpthread"]
+        );
+        assert_eq!(target.link_options, vec!["-Xlinker", "--no-undefined"]);
+    }
+
+    #[test]
+    fn unevaluated_warnings() {
+        let json = r#"
+{
+  "GENEX_TARGETS" :
+  {
+    "Foo::Core" :
+    {
+      "INTERFACE_INCLUDE_DIRECTORIES" : [ "$<TARGET_PROPERTY:Foo::Core,SOURCE_DIR>" ],
+      "LOCATION" : "/usr/lib/libfoo_core.so",
+      "NAME" : "Foo::Core"
+    }
+  },
+  "INTERFACE_COMPILE_OPTIONS" : [ "-Wall", "$<$<COMPILE_LANGUAGE:CXX>:-std=c++17>" ],
+  "INTERFACE_LINK_LIBRARIES" :
+  [
+    "$<$<TARGET_EXISTS:Foo::Core>:Foo::Core>",
+    "$<TARGET_NAME_IF_EXISTS:Foo::Missing>",
+    "$<TARGET_FILE:Foo::Tool>"
+  ],
+  "LOCATION" : "/usr/lib/libfoo.so",
+  "NAME" : "Foo::Foo"
+}
+"#;
+        let target: Target = serde_json::from_str(json).expect("Failed to parse JSON");
+        let target = target.evaluate(&genex::Context::default());
+
+        let mut buf = Vec::new();
+        target.warnings_write(&mut buf);
+        assert_eq!(
+            String::from_utf8(buf).unwrap().lines().collect::<Vec<_>>(),
+            [
+                "cargo:warning=Target Foo::Foo: ignoring $<TARGET_FILE:Foo::Tool> in INTERFACE_LINK_LIBRARIES, which can't be evaluated outside of CMake",
+                "cargo:warning=Target Foo::Foo: ignoring $<$<COMPILE_LANGUAGE:CXX>:-std=c++17> in INTERFACE_COMPILE_OPTIONS, which depends on the compiled language",
+                "cargo:warning=Target Foo::Core: ignoring $<TARGET_PROPERTY:Foo::Core,SOURCE_DIR> in INTERFACE_INCLUDE_DIRECTORIES, which can't be evaluated outside of CMake",
+            ]
+        );
+
+        let target = target.into_cmake_target(&CMakeBuildType::Release, LinkPreference::Default);
+        assert_eq!(target.compile_options, ["-Wall"]);
+        assert_eq!(
+            link_items(&target.link_libraries),
+            ["/usr/lib/libfoo.so", "/usr/lib/libfoo_core.so"]
+        );
+    }
+
+    #[test]
+    fn link_features_from_json() {
+        let json = r#"
+{
+  "GENEX_TARGETS" :
+  {
+    "Foo::plugin" :
+    {
+      "LOCATION" : "/usr/lib/libfoo_plugin.a",
+      "NAME" : "Foo::plugin",
+      "TYPE" : "STATIC_LIBRARY"
+    }
+  },
+  "INTERFACE_LINK_LIBRARIES" :
+  [
+    "$<LINK_LIBRARY:WHOLE_ARCHIVE,Foo::plugin>",
+    "$<LINK_GROUP:RESCAN,/usr/lib/liba.a,/usr/lib/libb.a>",
+    "m"
+  ],
+  "LOCATION" : "/usr/lib/libfoo.a",
+  "NAME" : "Foo::Foo",
+  "TYPE" : "STATIC_LIBRARY"
+}
+"#;
+        let target: Target = serde_json::from_str(json).expect("Failed to parse JSON");
+        let target = target
+            .evaluate(&genex::Context::default())
+            .into_cmake_target(&CMakeBuildType::Release, LinkPreference::Default);
+        let features = target
+            .link_libraries
+            .iter()
+            .map(|library| {
+                (
+                    library.item.as_str(),
+                    library.feature.as_deref(),
+                    library.group.as_deref(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            features,
+            vec![
+                ("/usr/lib/libfoo.a", None, None),
+                ("/usr/lib/libfoo_plugin.a", Some("WHOLE_ARCHIVE"), None),
+                ("/usr/lib/liba.a", None, Some("RESCAN")),
+                ("/usr/lib/libb.a", None, Some("RESCAN")),
+                ("m", None, None),
+            ]
+        );
+        assert_eq!(
+            target.link_libraries[1].target.as_deref(),
+            Some("Foo::plugin")
+        );
+    }
+
+    #[test]
+    fn package_config_files_from_json() {
+        let json = r#"
+{
+  "config_files" :
+  [
+    "/usr/lib/cmake/Foo/FooConfig.cmake",
+    "/usr/lib/cmake/Foo/FooTargets.cmake",
+    "/usr/lib/cmake/Foo/FooTargets-release.cmake",
+    "/usr/lib/cmake/Bar/BarConfig.cmake"
+  ],
+  "name" : "Foo",
+  "package_dir" : "/usr/lib/cmake/Foo"
+}
+"#;
+        let package: PackageResult = serde_json::from_str(json).expect("Failed to parse JSON");
+        assert_eq!(package.config_files.map(|files| files.len()), Some(4));
+        assert_eq!(package.package_dir, Some("/usr/lib/cmake/Foo".to_string()));
+    }
+
+    #[test]
+    fn cached_files() {
+        let json = r#"
+{
+  "GENEX_TARGETS" :
+  {
+    "Threads::Threads" : { "NAME" : "Threads::Threads" }
+  },
+  "IMPORTED_IMPLIBS" : { "RELEASE" : "C:/foo/lib/foo.lib" },
+  "INTERFACE_LINK_LIBRARIES" :
+  [
+    "-lm",
+    {
+      "IMPORTED_LOCATION" : "/usr/lib/libbar.so",
+      "NAME" : "Bar::Bar"
+    }
+  ],
+  "LOCATION" : "/usr/lib/libfoo.so",
+  "NAME" : "Foo::Foo"
+}
+"#;
+        let target: Target = serde_json::from_str(json).expect("Failed to parse JSON");
+        let mut files = Vec::new();
+        target.collect_files(&mut files);
+        assert_eq!(
+            files,
+            [
+                PathBuf::from("/usr/lib/libfoo.so"),
+                PathBuf::from("C:/foo/lib/foo.lib"),
+                PathBuf::from("/usr/lib/libbar.so"),
+            ]
+        );
+
+        // The cached target must be the same as the one read from the script output
+        let cached: Target =
+            serde_json::from_value(serde_json::to_value(&target).unwrap()).unwrap();
+        let mut cached_files = Vec::new();
+        cached.collect_files(&mut cached_files);
+        assert_eq!(cached_files, files);
+        assert_eq!(cached.genex_targets.len(), 1);
+    }
+
+    #[test]
+    fn cmake_project() {
+        let dir = tempfile::tempdir().unwrap();
+        setup_cmake_project(dir.path(), None).unwrap();
+        let script = std::fs::read_to_string(dir.path().join("CMakeLists.txt")).unwrap();
+        assert!(script.contains("function(find_package_wrapper)"));
+
+        let custom = dir.path().join("custom.cmake");
+        std::fs::write(&custom, "project(custom)").unwrap();
+        setup_cmake_project(dir.path(), Some(&custom)).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("CMakeLists.txt")).unwrap(),
+            "project(custom)"
+        );
+    }
+
+    #[test]
+    fn env_vars() {
+        assert_eq!(
+            tracked_env_vars("OpenSSL")[..11],
+            [
+                "CMAKE_PREFIX_PATH",
+                "OpenSSL_ROOT",
+                "OPENSSL_ROOT",
+                "OpenSSL_DIR",
+                "PATH",
+                "PROFILE",
+                "OPT_LEVEL",
+                "DEBUG",
+                "CMAKE_PACKAGE_BUILD_TYPE",
+                "CMAKE_PACKAGE_RECORD",
+                "CMAKE_PACKAGE_REPLAY"
+            ]
+        );
+        for var in ["CC", "HOST_CXX", "CFLAGS", "CXXFLAGS"] {
+            assert!(tracked_env_vars("OpenSSL").contains(&var.to_string()));
+        }
+        assert!(!tracked_env_vars("ZLIB").contains(&"zlib_ROOT".to_string()));
+        assert_eq!(
+            tracked_env_vars("ZLIB")
+                .iter()
+                .filter(|var| var.ends_with("_ROOT"))
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn package_from_json() {
+        let json = r#"
+{
+  "components" : [ "Core", "Gui" ],
+  "name" : "Qt6",
+  "targets" : [ "Qt6::Core", "Qt6::Gui", "Threads::Threads" ],
+  "version" : "6.7.2"
+}
+"#;
+        let package: PackageResult = serde_json::from_str(json).expect("Failed to parse JSON");
+        assert_eq!(package.name, Some("Qt6".to_string()));
+        assert_eq!(package.version, Some("6.7.2".to_string()));
+        assert_eq!(
+            package.components,
+            Some(vec!["Core".to_string(), "Gui".to_string()])
+        );
+        assert_eq!(
+            package.targets,
+            Some(vec![
+                "Qt6::Core".to_string(),
+                "Qt6::Gui".to_string(),
+                "Threads::Threads".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn options_args() {
+        let options = CMakeOptions {
+            prefix_paths: vec!["/opt/foo".into(), "/opt/bar".into()],
+            package_dir: Some("/opt/foo/lib/cmake/Foo".into()),
+            package_root: Some("/opt/foo".into()),
+            defines: vec![
+                ("OPENSSL_USE_STATIC_LIBS".into(), "ON".into()),
+                ("Foo_NO_EXTRAS".into(), "TRUE".into()),
+            ],
+            languages: Some(vec!["C".into(), "CXX".into()]),
+            toolchain_file: Some("/opt/toolchain.cmake".into()),
+            ..Default::default()
+        };
+
+        let mut command = Command::new("cmake");
+        options.apply("Foo", &mut command);
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            [
+                "-DCMAKE_PREFIX_PATH=/opt/foo;/opt/bar",
+                "-DFoo_DIR=/opt/foo/lib/cmake/Foo",
+                "-DFoo_ROOT=/opt/foo",
+                "-DOPENSSL_USE_STATIC_LIBS=ON",
+                "-DFoo_NO_EXTRAS=TRUE",
+                "-DCMAKE_TOOLCHAIN_FILE=/opt/toolchain.cmake",
+                "-DLANGUAGES=C;CXX"
+            ]
+        );
+
+        let mut command = Command::new("cmake");
+        CMakeOptions {
+            languages: Some(vec![]),
+            ..Default::default()
+        }
+        .apply("Foo", &mut command);
+        assert_eq!(command.get_args().collect::<Vec<_>>(), ["-DLANGUAGES=NONE"]);
+
+        let mut command = Command::new("cmake");
+        CMakeOptions::default().apply("Foo", &mut command);
+        assert_eq!(command.get_args().count(), 0);
+    }
+
+    #[test]
+    fn from_targets_json_not_found() {
+        let json = r#"
+{
+  "not_found" : [ "OpenSSL::Foo" ],
+  "targets" :
+  {
+    "OpenSSL::SSL" :
+    {
+      "LOCATION" : "/usr/lib/libssl.so",
+      "NAME" : "OpenSSL::SSL"
+    }
+  }
+}
+"#;
+        let result: TargetsResult = serde_json::from_str(json).expect("Failed to parse JSON");
+        assert_eq!(result.targets.len(), 1);
+        assert_eq!(result.not_found, vec!["OpenSSL::Foo"]);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn script_failure() {
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo output; echo error message >&2; exit 3"]);
+        match run(&mut command, false).expect_err("Command should fail") {
+            Error::ScriptFailed {
+                command,
+                status,
+                stdout,
+                stderr,
+            } => {
+                assert_eq!(command, "sh -c echo output; echo error message >&2; exit 3");
+                assert_eq!(status, Some(3));
+                assert_eq!(stdout, "output\n");
+                assert_eq!(stderr, "error message\n");
+            }
+            err => panic!("Unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn error_display() {
+        let err = Error::ScriptFailed {
+            command: "cmake . -DPACKAGE=Foo".to_string(),
+            status: Some(1),
+            stdout: String::new(),
+            stderr: "CMake Error: Package Foo not found\n".to_string(),
+        };
+        assert_eq!(
+            err.to_string(),
+            "`cmake . -DPACKAGE=Foo` failed with exit code 1:\nCMake Error: Package Foo not found"
+        );
+
+        let err = Error::InvalidOutput {
+            path: PathBuf::from("package.json"),
+            source: serde_json::from_str::<PackageResult>("{").unwrap_err(),
+        };
+        assert!(err
+            .to_string()
+            .starts_with("failed to parse package.json: "));
+        assert!(std::error::Error::source(&err).is_some());
+
+        let err = Error::TargetNotFound {
+            package: "OpenSSL".to_string(),
+            target: "OpenSSL::Foo".to_string(),
+            available: vec!["OpenSSL::SSL".to_string(), "OpenSSL::Crypto".to_string()],
+        };
+        assert_eq!(
+            err.to_string(),
+            "target OpenSSL::Foo not found in package OpenSSL (available targets: OpenSSL::SSL, OpenSSL::Crypto)"
+        );
+    }
+
+    #[test]
+    fn missing_compiler() {
+        let failure = |stderr: &str| Error::ScriptFailed {
+            command: "cmake .".to_string(),
+            status: Some(1),
+            stdout: String::new(),
+            stderr: stderr.to_string(),
+        };
+
+        let err = compiler_not_found(failure(
+            "CMake Error at CMakeLists.txt:102 (project):\n  No CMAKE_CXX_COMPILER could be found.\n",
+        ));
+        match &err {
+            Error::CompilerNotFound { language, .. } => assert_eq!(language, "CXX"),
+            err => panic!("Unexpected error: {:?}", err),
+        }
+        assert!(err
+            .to_string()
+            .starts_with("no compiler for language CXX found"));
+
+        let err = compiler_not_found(failure(
+            "CMake Error at /usr/share/cmake/Modules/FindThreads.cmake:66 (message):\n  \
+             FindThreads only works if either C or CXX language is enabled\n",
+        ));
+        assert!(matches!(err, Error::CompilerNotFound { language, .. } if language == "C"));
+
+        assert!(matches!(
+            compiler_not_found(failure("CMake Error: Package Foo not found\n")),
+            Error::ScriptFailed { .. }
+        ));
+    }
+
     fn clear_env(name: &'static str) -> ScopeGuard<(), impl FnOnce(())> {
         let value = std::env::var(name);
         std::env::remove_var(name);
@@ -614,4 +2422,74 @@ mod testing {
         std::env::set_var("OPT_LEVEL", "s");
         assert_eq!(build_type(), CMakeBuildType::MinSizeRel);
     }
+
+    #[test]
+    #[serial]
+    fn test_resolve_build_type() {
+        let _profile = clear_env("PROFILE");
+        let _override = clear_env(BUILD_TYPE_ENV);
+
+        assert_eq!(resolve_build_type(None), CMakeBuildType::Debug);
+        assert_eq!(
+            resolve_build_type(Some(CMakeBuildType::Release)),
+            CMakeBuildType::Release
+        );
+
+        std::env::set_var(BUILD_TYPE_ENV, "");
+        assert_eq!(
+            resolve_build_type(Some(CMakeBuildType::Release)),
+            CMakeBuildType::Release
+        );
+
+        std::env::set_var(BUILD_TYPE_ENV, "profile");
+        assert_eq!(
+            resolve_build_type(Some(CMakeBuildType::Release)),
+            CMakeBuildType::Custom("profile".to_string())
+        );
+    }
+
+    #[test]
+    fn build_type_names() {
+        for build_type in [
+            CMakeBuildType::Debug,
+            CMakeBuildType::Release,
+            CMakeBuildType::RelWithDebInfo,
+            CMakeBuildType::MinSizeRel,
+            CMakeBuildType::Custom("RelWithAssert".to_string()),
+        ] {
+            assert_eq!(
+                build_type.to_string().parse::<CMakeBuildType>(),
+                Ok(build_type)
+            );
+        }
+        assert_eq!(CMakeBuildType::from("release"), CMakeBuildType::Release);
+        assert_eq!(
+            CMakeBuildType::from("RELWITHDEBINFO"),
+            CMakeBuildType::RelWithDebInfo
+        );
+        assert_eq!(
+            CMakeBuildType::from("Profile").to_string(),
+            "Profile".to_string()
+        );
+    }
+
+    #[test]
+    fn custom_build_type() {
+        let target = Target {
+            name: "Foo::Foo".to_string(),
+            imported_configurations: vec!["RELEASE".to_string(), "PROFILE".to_string()],
+            imported_locations: HashMap::from([
+                ("RELEASE".to_string(), "/usr/lib/libfoo.so".to_string()),
+                (
+                    "PROFILE".to_string(),
+                    "/usr/lib/libfoo_profile.so".to_string(),
+                ),
+            ]),
+            ..Default::default()
+        };
+        assert_eq!(
+            location_for_build_type(&CMakeBuildType::from("Profile"), &target).as_deref(),
+            Some("/usr/lib/libfoo_profile.so")
+        );
+    }
 }
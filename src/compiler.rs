@@ -0,0 +1,217 @@
+// SPDX-FileCopyrightText: 2024 Daniel Vrátil <dvratil@kde.org>
+//
+// SPDX-License-Identifier: MIT
+
+//! Forwarding of the compiler configuration of the [cc][cc_crate] crate to CMake.
+//!
+//! CMake uses the first compiler it finds when it enables a language, which may not be the compiler
+//! that is used to build the C/C++ code of the crate, e.g. when cross-compiling. The compilers and
+//! the flags are therefore looked up in the same environment variables and in the same order as the
+//! `cc` crate does, and passed to CMake as `CMAKE_<LANG>_COMPILER` and `CMAKE_<LANG>_FLAGS`.
+//!
+//! [cc_crate]: https://docs.rs/cc/latest/cc/#external-configuration-via-environment-variables
+
+use std::path::Path;
+
+/// CMake name of the language, the compiler variable and the flags variable for each language.
+const LANGUAGES: [(&str, &str, &str); 2] = [("C", "CC", "CFLAGS"), ("CXX", "CXX", "CXXFLAGS")];
+
+/// Compiler wrappers recognized by the `cc` crate, passed to CMake as `CMAKE_<LANG>_COMPILER_LAUNCHER`.
+const WRAPPERS: [&str; 6] = [
+    "ccache",
+    "distcc",
+    "sccache",
+    "icecc",
+    "cachepot",
+    "buildcache",
+];
+
+/// Returns names of the environment variables for `var` in the order of precedence, the same way
+/// as the `cc` crate: `<var>_<target>`, `<var>_<target_with_underscores>`, `HOST_<var>` or
+/// `TARGET_<var>` and `<var>`.
+pub(crate) fn var_names(var: &str, target: Option<&str>, host: Option<&str>) -> Vec<String> {
+    let kind = if target == host { "HOST" } else { "TARGET" };
+    target
+        .into_iter()
+        .flat_map(|target| {
+            [
+                format!("{var}_{target}"),
+                format!("{var}_{}", target.replace('-', "_")),
+            ]
+        })
+        .chain([format!("{kind}_{var}"), var.to_string()])
+        .collect()
+}
+
+/// Returns names of all environment variables that affect the compilers.
+pub(crate) fn env_vars() -> Vec<String> {
+    let target = std::env::var("TARGET").ok();
+    let host = std::env::var("HOST").ok();
+    LANGUAGES
+        .iter()
+        .flat_map(|(_, compiler, flags)| [compiler, flags])
+        .flat_map(|var| var_names(var, target.as_deref(), host.as_deref()))
+        .collect()
+}
+
+/// Returns the `-D` arguments for CMake with the compilers and flags configured in the environment.
+pub(crate) fn cmake_args() -> Vec<String> {
+    cmake_args_with(|var| std::env::var(var).ok(), Path::exists)
+}
+
+fn cmake_args_with(
+    getenv: impl Fn(&str) -> Option<String>,
+    exists: impl Fn(&Path) -> bool,
+) -> Vec<String> {
+    let target = getenv("TARGET");
+    let host = getenv("HOST");
+    let lookup = |var: &str| {
+        var_names(var, target.as_deref(), host.as_deref())
+            .iter()
+            .find_map(|name| getenv(name))
+            .filter(|value| !value.trim().is_empty())
+    };
+
+    let mut args = Vec::new();
+    for (language, compiler_var, flags_var) in LANGUAGES {
+        let mut flags = Vec::new();
+        if let Some(compiler) = lookup(compiler_var) {
+            // A path to the compiler may contain spaces, so it's only split when it's not a path
+            let mut parts: Box<dyn Iterator<Item = &str>> = if exists(Path::new(&compiler)) {
+                Box::new(std::iter::once(compiler.as_str()))
+            } else {
+                Box::new(compiler.split_whitespace())
+            };
+            let mut compiler = parts.next().unwrap_or_default();
+            let is_wrapper = Path::new(compiler)
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .is_some_and(|stem| WRAPPERS.contains(&stem));
+            if is_wrapper {
+                if let Some(wrapped) = parts.next() {
+                    args.push(format!("-DCMAKE_{language}_COMPILER_LAUNCHER={compiler}"));
+                    compiler = wrapped;
+                }
+            }
+            args.push(format!("-DCMAKE_{language}_COMPILER={compiler}"));
+            // Any additional arguments are passed to the compiler with the flags
+            flags.extend(parts.map(ToString::to_string));
+        }
+        flags.extend(lookup(flags_var));
+        if !flags.is_empty() {
+            args.push(format!("-DCMAKE_{language}_FLAGS={}", flags.join(" ")));
+        }
+    }
+    args
+}
+
+#[cfg(test)]
+mod testing {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn args(env: &[(&str, &str)]) -> Vec<String> {
+        args_with_files(env, &[])
+    }
+
+    fn args_with_files(env: &[(&str, &str)], files: &[&str]) -> Vec<String> {
+        let env: HashMap<&str, &str> = env.iter().copied().collect();
+        cmake_args_with(
+            |var| env.get(var).map(ToString::to_string),
+            |path| files.iter().any(|file| Path::new(file) == path),
+        )
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(
+            var_names(
+                "CC",
+                Some("aarch64-linux-android"),
+                Some("x86_64-unknown-linux-gnu")
+            ),
+            [
+                "CC_aarch64-linux-android",
+                "CC_aarch64_linux_android",
+                "TARGET_CC",
+                "CC"
+            ]
+        );
+        assert_eq!(
+            var_names(
+                "CFLAGS",
+                Some("x86_64-unknown-linux-gnu"),
+                Some("x86_64-unknown-linux-gnu")
+            ),
+            [
+                "CFLAGS_x86_64-unknown-linux-gnu",
+                "CFLAGS_x86_64_unknown_linux_gnu",
+                "HOST_CFLAGS",
+                "CFLAGS"
+            ]
+        );
+        assert_eq!(var_names("CXX", None, None), ["HOST_CXX", "CXX"]);
+    }
+
+    #[test]
+    fn compilers() {
+        assert!(args(&[]).is_empty());
+
+        assert_eq!(
+            args(&[("CC", "clang"), ("CXX", "clang++"), ("CFLAGS", "-O2 -g")]),
+            [
+                "-DCMAKE_C_COMPILER=clang",
+                "-DCMAKE_C_FLAGS=-O2 -g",
+                "-DCMAKE_CXX_COMPILER=clang++",
+            ]
+        );
+
+        // Target-specific variables take precedence
+        assert_eq!(
+            args(&[
+                ("TARGET", "aarch64-unknown-linux-gnu"),
+                ("HOST", "x86_64-unknown-linux-gnu"),
+                ("CC", "gcc"),
+                ("TARGET_CC", "cc"),
+                ("CC_aarch64_unknown_linux_gnu", "aarch64-linux-gnu-gcc"),
+                ("CXX", "g++"),
+                ("TARGET_CXX", "aarch64-linux-gnu-g++"),
+            ]),
+            [
+                "-DCMAKE_C_COMPILER=aarch64-linux-gnu-gcc",
+                "-DCMAKE_CXX_COMPILER=aarch64-linux-gnu-g++",
+            ]
+        );
+
+        // Compiler wrappers and extra arguments
+        assert_eq!(
+            args(&[("CC", "sccache gcc -m32"), ("CFLAGS", "-O2"), ("CXX", " ")]),
+            [
+                "-DCMAKE_C_COMPILER_LAUNCHER=sccache",
+                "-DCMAKE_C_COMPILER=gcc",
+                "-DCMAKE_C_FLAGS=-m32 -O2",
+            ]
+        );
+
+        // A path with spaces is split only when it doesn't exist
+        assert_eq!(
+            args_with_files(
+                &[
+                    ("CC", r"C:\Program Files\LLVM\bin\clang.exe"),
+                    ("CXX", "clang++ -stdlib=libc++"),
+                ],
+                &[r"C:\Program Files\LLVM\bin\clang.exe"]
+            ),
+            [
+                r"-DCMAKE_C_COMPILER=C:\Program Files\LLVM\bin\clang.exe",
+                "-DCMAKE_CXX_COMPILER=clang++",
+                "-DCMAKE_CXX_FLAGS=-stdlib=libc++",
+            ]
+        );
+        assert_eq!(
+            args(&[("CC", "/opt/my tools/gcc")])[..2],
+            ["-DCMAKE_C_COMPILER=/opt/my", "-DCMAKE_C_FLAGS=tools/gcc"]
+        );
+    }
+}
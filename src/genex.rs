@@ -0,0 +1,720 @@
+// SPDX-FileCopyrightText: 2024 Daniel Vrátil <dvratil@kde.org>
+//
+// SPDX-License-Identifier: MIT
+
+//! Evaluator for [CMake generator expressions][cmake_generator_expr].
+//!
+//! CMake evaluates generator expressions only when generating the build system, which
+//! happens after the "configure" step that this crate runs. The CMake script therefore
+//! passes the expressions through verbatim and they are evaluated here instead, using
+//! the build configuration and the information about the toolchain reported by CMake.
+//!
+//! Only the expressions that make sense for a consumer of an imported target are supported,
+//! all other expressions evaluate to an empty string and are reported to the caller.
+//!
+//! [cmake_generator_expr]: https://cmake.org/cmake/help/latest/manual/cmake-generator-expressions.7.html
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+/// Languages that the consumers of the targets are expected to compile.
+const COMPILE_LANGUAGES: [&str; 2] = ["C", "CXX"];
+
+/// Information needed to evaluate generator expressions.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, rename_all = "UPPERCASE")]
+pub(crate) struct Context {
+    /// The build configuration (`$<CONFIG>`), set by the Rust code.
+    #[serde(skip)]
+    pub(crate) config: String,
+    /// The target platform (`$<PLATFORM_ID>`).
+    pub(crate) platform_id: String,
+    /// ID of the C compiler (`$<C_COMPILER_ID>`).
+    #[serde(rename = "C_COMPILER_ID")]
+    pub(crate) c_compiler_id: String,
+    /// Version of the C compiler (`$<C_COMPILER_VERSION>`).
+    #[serde(rename = "C_COMPILER_VERSION")]
+    pub(crate) c_compiler_version: String,
+    /// ID of the C++ compiler (`$<CXX_COMPILER_ID>`), empty when C++ is not enabled.
+    #[serde(rename = "CXX_COMPILER_ID")]
+    pub(crate) cxx_compiler_id: String,
+    /// Version of the C++ compiler (`$<CXX_COMPILER_VERSION>`).
+    #[serde(rename = "CXX_COMPILER_VERSION")]
+    pub(crate) cxx_compiler_version: String,
+    /// The language being compiled (`$<COMPILE_LANGUAGE>`), `None` for any of the `COMPILE_LANGUAGES`.
+    #[serde(skip)]
+    pub(crate) compile_language: Option<String>,
+    /// Targets known to exist (`$<TARGET_EXISTS:...>`), i.e. the evaluated target and the targets
+    /// referenced by its generator expressions.
+    #[serde(skip)]
+    pub(crate) targets: HashSet<String>,
+    /// The [`CMAKE_C_LINKER_WRAPPER_FLAG`][cmake_linker_wrapper_flag] used to expand the `LINKER:`
+    /// prefix in link options, `None` when no C compiler is enabled.
+    ///
+    /// [cmake_linker_wrapper_flag]: https://cmake.org/cmake/help/latest/variable/CMAKE_LANG_LINKER_WRAPPER_FLAG.html
+    pub(crate) linker_wrapper_flag: Option<String>,
+    /// The `CMAKE_C_LINKER_WRAPPER_FLAG_SEP`.
+    pub(crate) linker_wrapper_flag_sep: Option<String>,
+}
+
+impl Context {
+    /// Whether the `language` is compiled (or linked, for the `LINK_*` expression `name`).
+    fn is_compiled(&self, name: &str, language: &str) -> bool {
+        match &self.compile_language {
+            Some(compiled) if name.starts_with("COMPILE_") => compiled == language,
+            _ => COMPILE_LANGUAGES.contains(&language),
+        }
+    }
+
+    fn compiler_id(&self, language: &str) -> Option<&str> {
+        match language {
+            "C" => Some(&self.c_compiler_id),
+            "CXX" => Some(&self.cxx_compiler_id),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Text(String),
+    Expression {
+        name: Vec<Node>,
+        parameters: Option<Vec<Vec<Node>>>,
+    },
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str) -> Self {
+        Self { input, position: 0 }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.position..].chars().next()
+    }
+
+    /// Parses the input until one of the `terminators` is found outside of a nested expression,
+    /// or until the end of the input.
+    fn parse_until(&mut self, terminators: &[char]) -> Vec<Node> {
+        let mut nodes = Vec::new();
+        let mut text = String::new();
+        while let Some(c) = self.peek() {
+            if self.input[self.position..].starts_with("$<") {
+                if !text.is_empty() {
+                    nodes.push(Node::Text(std::mem::take(&mut text)));
+                }
+                self.position += 2;
+                nodes.push(self.parse_expression());
+            } else if terminators.contains(&c) {
+                break;
+            } else {
+                text.push(c);
+                self.position += c.len_utf8();
+            }
+        }
+        if !text.is_empty() {
+            nodes.push(Node::Text(text));
+        }
+        nodes
+    }
+
+    /// Parses a single expression, the leading `$<` has already been consumed.
+    fn parse_expression(&mut self) -> Node {
+        let name = self.parse_until(&[':', '>']);
+        let parameters = if self.peek() == Some(':') {
+            self.position += 1;
+            let mut parameters = vec![self.parse_until(&[',', '>'])];
+            while self.peek() == Some(',') {
+                self.position += 1;
+                parameters.push(self.parse_until(&[',', '>']));
+            }
+            Some(parameters)
+        } else {
+            None
+        };
+        // Unterminated expressions are tolerated, they simply end with the input
+        if self.peek() == Some('>') {
+            self.position += 1;
+        }
+        Node::Expression { name, parameters }
+    }
+}
+
+/// Returns `true` if the `value` contains a generator expression.
+pub(crate) fn is_expression(value: &str) -> bool {
+    value.contains("$<")
+}
+
+/// Features of a library given by the [`$<LINK_LIBRARY:...>`][cmake_link_library] and
+/// [`$<LINK_GROUP:...>`][cmake_link_group] expressions.
+///
+/// [cmake_link_library]: https://cmake.org/cmake/help/latest/manual/cmake-generator-expressions.7.html#genex:LINK_LIBRARY
+/// [cmake_link_group]: https://cmake.org/cmake/help/latest/manual/cmake-generator-expressions.7.html#genex:LINK_GROUP
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct LinkFeatures {
+    /// The library feature, e.g. `WHOLE_ARCHIVE`.
+    pub(crate) feature: Option<String>,
+    /// The group feature, e.g. `RESCAN`.
+    pub(crate) group: Option<String>,
+}
+
+/// Splits a library evaluated for linking into the library itself and the features it is
+/// linked with, e.g. `$<LINK_GROUP:RESCAN,$<LINK_LIBRARY:WHOLE_ARCHIVE,foo>>` into `foo`
+/// with the `WHOLE_ARCHIVE` feature in the `RESCAN` group.
+pub(crate) fn strip_link_features(mut item: &str) -> (&str, LinkFeatures) {
+    let mut features = LinkFeatures::default();
+    while let Some((name, feature, library)) = item
+        .strip_prefix("$<")
+        .and_then(|item| item.strip_suffix('>'))
+        .and_then(|item| {
+            let (name, item) = item.split_once(':')?;
+            let (feature, library) = item.split_once(',')?;
+            Some((name, feature, library))
+        })
+    {
+        match name {
+            "LINK_LIBRARY" => features.feature = Some(feature.to_string()),
+            "LINK_GROUP" => features.group = Some(feature.to_string()),
+            _ => break,
+        }
+        item = library;
+    }
+    (item, features)
+}
+
+/// Evaluates all generator expressions in the `value`.
+///
+/// When `linking` is `true`, the value is evaluated for the purpose of linking (e.g. `$<LINK_ONLY:...>`
+/// evaluates to its content), otherwise it is evaluated for the purpose of compiling.
+///
+/// Expressions that can't be evaluated outside of CMake (e.g. `$<TARGET_PROPERTY:...>`) evaluate
+/// to an empty string and are added to `unsupported`.
+pub(crate) fn evaluate(
+    value: &str,
+    context: &Context,
+    linking: bool,
+    unsupported: &mut Vec<String>,
+) -> String {
+    let nodes = Parser::new(value).parse_until(&[]);
+    evaluate_nodes(&nodes, context, linking, unsupported)
+}
+
+/// Evaluates the `value` for compilation of any of the `COMPILE_LANGUAGES` and splits it into
+/// a list like [`evaluate_list()`].
+///
+/// Returns `None` if the value differs between the languages, e.g. `$<$<COMPILE_LANGUAGE:CXX>:-std=c++17>`,
+/// since it can't be passed to the compilers of all the languages at once.
+pub(crate) fn evaluate_compile_list(
+    value: &str,
+    context: &Context,
+    unsupported: &mut Vec<String>,
+) -> Option<Vec<String>> {
+    let mut results = COMPILE_LANGUAGES
+        .iter()
+        .map(|language| {
+            let context = Context {
+                compile_language: Some(language.to_string()),
+                ..context.clone()
+            };
+            evaluate_list(value, &context, false, unsupported)
+        })
+        .collect::<Vec<_>>()
+        .into_iter();
+    let first = results.next().unwrap_or_default();
+    results.all(|result| result == first).then_some(first)
+}
+
+/// Same as [`evaluate()`], but splits the result into a list of non-empty items like CMake does.
+pub(crate) fn evaluate_list(
+    value: &str,
+    context: &Context,
+    linking: bool,
+    unsupported: &mut Vec<String>,
+) -> Vec<String> {
+    evaluate(value, context, linking, unsupported)
+        .split(';')
+        .filter(|item| !item.is_empty())
+        .map(ToString::to_string)
+        .collect()
+}
+
+fn evaluate_nodes(
+    nodes: &[Node],
+    context: &Context,
+    linking: bool,
+    unsupported: &mut Vec<String>,
+) -> String {
+    let mut result = String::new();
+    for node in nodes {
+        match node {
+            Node::Text(text) => result.push_str(text),
+            Node::Expression { name, parameters } => {
+                let name = evaluate_nodes(name, context, linking, unsupported);
+                let mut evaluate = |parameter: &Vec<Node>| {
+                    evaluate_nodes(parameter, context, linking, unsupported)
+                };
+                // Like CMake, the branches not taken are not evaluated, so that e.g.
+                // `$<$<TARGET_EXISTS:foo>:$<TARGET_PROPERTY:foo,BAR>>` is not reported as unsupported
+                let parameters = match (name.as_str(), parameters.as_deref()) {
+                    ("0", Some(_)) => Some(Vec::new()),
+                    ("IF", Some([condition, if_true, if_false])) => {
+                        let condition = evaluate(condition);
+                        let (if_true, if_false) = if condition == "1" {
+                            (evaluate(if_true), String::new())
+                        } else {
+                            (String::new(), evaluate(if_false))
+                        };
+                        Some(vec![condition, if_true, if_false])
+                    }
+                    (_, parameters) => {
+                        parameters.map(|parameters| parameters.iter().map(&mut evaluate).collect())
+                    }
+                };
+                result.push_str(&evaluate_expression(
+                    &name,
+                    parameters.as_deref(),
+                    context,
+                    linking,
+                    unsupported,
+                ));
+            }
+        }
+    }
+    result
+}
+
+fn evaluate_expression(
+    name: &str,
+    parameters: Option<&[String]>,
+    context: &Context,
+    linking: bool,
+    unsupported: &mut Vec<String>,
+) -> String {
+    // Expressions that take a single parameter treat commas as part of the value
+    let content = || parameters.map(|p| p.join(",")).unwrap_or_default();
+
+    match (name, parameters) {
+        // Conditional expressions
+        ("0", _) => String::new(),
+        ("1", _) => content(),
+        ("IF", Some([condition, if_true, if_false])) => {
+            if condition == "1" {
+                if_true.clone()
+            } else {
+                if_false.clone()
+            }
+        }
+
+        // Logical operators
+        ("BOOL", _) => bool_str(is_true(&content())),
+        ("NOT", _) => bool_str(content() == "0"),
+        ("AND", Some(values)) => bool_str(values.iter().all(|v| v == "1")),
+        ("OR", Some(values)) => bool_str(values.iter().any(|v| v == "1")),
+
+        // Comparisons
+        ("STREQUAL", Some([a, b])) => bool_str(a == b),
+        ("EQUAL", Some([a, b])) => bool_str(matches!(
+            (a.parse::<i64>(), b.parse::<i64>()),
+            (Ok(a), Ok(b)) if a == b
+        )),
+        ("IN_LIST", Some([value, list])) => bool_str(list.split(';').any(|item| item == value)),
+        ("VERSION_LESS", Some([a, b])) => bool_str(compare_versions(a, b) == Ordering::Less),
+        ("VERSION_GREATER", Some([a, b])) => bool_str(compare_versions(a, b) == Ordering::Greater),
+        ("VERSION_EQUAL", Some([a, b])) => bool_str(compare_versions(a, b) == Ordering::Equal),
+        ("VERSION_LESS_EQUAL", Some([a, b])) => {
+            bool_str(compare_versions(a, b) != Ordering::Greater)
+        }
+        ("VERSION_GREATER_EQUAL", Some([a, b])) => {
+            bool_str(compare_versions(a, b) != Ordering::Less)
+        }
+
+        // String transformations
+        ("LOWER_CASE", _) => content().to_lowercase(),
+        ("UPPER_CASE", _) => content().to_uppercase(),
+
+        // Build configuration and toolchain queries
+        ("CONFIG", None) => context.config.clone(),
+        ("CONFIG", Some(configs)) => bool_str(
+            configs
+                .iter()
+                .any(|config| config.eq_ignore_ascii_case(&context.config)),
+        ),
+        ("PLATFORM_ID", None) => context.platform_id.clone(),
+        ("PLATFORM_ID", Some(platforms)) => bool_str(platforms.contains(&context.platform_id)),
+        ("C_COMPILER_ID", None) => context.c_compiler_id.clone(),
+        ("C_COMPILER_ID", Some(ids)) => bool_str(ids.contains(&context.c_compiler_id)),
+        ("CXX_COMPILER_ID", None) => context.cxx_compiler_id.clone(),
+        ("CXX_COMPILER_ID", Some(ids)) => bool_str(ids.contains(&context.cxx_compiler_id)),
+        ("C_COMPILER_VERSION", None) => context.c_compiler_version.clone(),
+        ("C_COMPILER_VERSION", Some([version])) => {
+            bool_str(compare_versions(version, &context.c_compiler_version) == Ordering::Equal)
+        }
+        ("CXX_COMPILER_VERSION", None) => context.cxx_compiler_version.clone(),
+        ("CXX_COMPILER_VERSION", Some([version])) => {
+            bool_str(compare_versions(version, &context.cxx_compiler_version) == Ordering::Equal)
+        }
+
+        // Unless the compiled language is given, the consumer may compile (and link) any of the
+        // COMPILE_LANGUAGES.
+        ("COMPILE_LANGUAGE", None) => context.compile_language.clone().unwrap_or_default(),
+        ("COMPILE_LANGUAGE" | "LINK_LANGUAGE", Some(languages)) => bool_str(
+            languages
+                .iter()
+                .any(|language| context.is_compiled(name, language)),
+        ),
+        ("COMPILE_LANG_AND_ID" | "LINK_LANG_AND_ID", Some([language, ids @ ..])) => {
+            bool_str(context.compiler_id(language).is_some_and(|compiler_id| {
+                context.is_compiled(name, language) && ids.iter().any(|id| id == compiler_id)
+            }))
+        }
+
+        // Usage requirements
+        ("LINK_ONLY", _) => {
+            if linking {
+                content()
+            } else {
+                String::new()
+            }
+        }
+        // Imported targets are part of the consumer's build system
+        ("BUILD_INTERFACE" | "BUILD_LOCAL_INTERFACE", _) => content(),
+        ("INSTALL_INTERFACE", _) => String::new(),
+        ("HOST_LINK", _) => content(),
+        // The features only matter for linking, where each library is kept wrapped in the expression
+        // for `strip_link_features()` to pick up
+        ("LINK_LIBRARY" | "LINK_GROUP", Some([feature, libraries @ ..])) => libraries
+            .iter()
+            .flat_map(|libraries| libraries.split(';'))
+            .filter(|library| !library.is_empty())
+            .map(|library| {
+                if linking {
+                    format!("$<{name}:{feature},{library}>")
+                } else {
+                    library.to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(";"),
+        ("DEVICE_LINK", _) => String::new(),
+        ("TARGET_NAME", _) => content(),
+        ("TARGET_EXISTS", Some([target])) => bool_str(context.targets.contains(target)),
+        ("TARGET_NAME_IF_EXISTS", Some([target])) if context.targets.contains(target) => {
+            target.clone()
+        }
+        ("TARGET_NAME_IF_EXISTS", Some([_])) => String::new(),
+
+        // Escaped characters
+        ("ANGLE-R", None) => ">".to_string(),
+        ("COMMA", None) => ",".to_string(),
+        ("SEMICOLON", None) => ";".to_string(),
+        ("QUOTE", None) => "\"".to_string(),
+
+        // Anything else cannot be evaluated outside of CMake
+        _ => {
+            unsupported.push(match parameters {
+                Some(parameters) => format!("$<{name}:{}>", parameters.join(",")),
+                None => format!("$<{name}>"),
+            });
+            String::new()
+        }
+    }
+}
+
+fn bool_str(value: bool) -> String {
+    if value { "1" } else { "0" }.to_string()
+}
+
+/// Implements the CMake's `$<BOOL:...>` logic.
+fn is_true(value: &str) -> bool {
+    !(value.is_empty()
+        || ["0", "FALSE", "OFF", "N", "NO", "IGNORE", "NOTFOUND"]
+            .iter()
+            .any(|false_value| value.eq_ignore_ascii_case(false_value))
+        || value.ends_with("-NOTFOUND"))
+}
+
+/// Compares two dot-separated version strings component by component, missing components
+/// are treated as zeros.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let components = |version: &str| -> Vec<u64> {
+        version
+            .split('.')
+            .map(|component| component.trim().parse().unwrap_or(0))
+            .collect()
+    };
+    let (a, b) = (components(a), components(b));
+    (0..a.len().max(b.len()))
+        .map(|i| a.get(i).unwrap_or(&0).cmp(b.get(i).unwrap_or(&0)))
+        .find(|ordering| *ordering != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+
+    fn context() -> Context {
+        Context {
+            config: "Debug".to_string(),
+            platform_id: "Linux".to_string(),
+            c_compiler_id: "GNU".to_string(),
+            c_compiler_version: "14.2.1".to_string(),
+            cxx_compiler_id: "GNU".to_string(),
+            cxx_compiler_version: "14.2.1".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn eval(value: &str) -> String {
+        evaluate(value, &context(), false, &mut Vec::new())
+    }
+
+    #[test]
+    fn plain_text() {
+        assert_eq!(eval("/usr/include"), "/usr/include");
+        assert_eq!(eval("a>b,c:d"), "a>b,c:d");
+        assert!(!is_expression("/usr/include"));
+        assert!(is_expression("$<CONFIG>"));
+    }
+
+    #[test]
+    fn config() {
+        assert_eq!(eval("$<CONFIG>"), "Debug");
+        assert_eq!(eval("$<$<CONFIG:Debug>:FOO_DEBUG>"), "FOO_DEBUG");
+        assert_eq!(eval("$<$<CONFIG:debug>:FOO_DEBUG>"), "FOO_DEBUG");
+        assert_eq!(eval("$<$<CONFIG:Release,MinSizeRel>:FOO_RELEASE>"), "");
+        assert_eq!(eval("$<$<NOT:$<CONFIG:Debug>>:NDEBUG>"), "");
+        assert_eq!(
+            eval("-DMODE=$<IF:$<CONFIG:Debug>,debug,release>"),
+            "-DMODE=debug"
+        );
+    }
+
+    #[test]
+    fn logical_operators() {
+        assert_eq!(eval("$<BOOL:ON>"), "1");
+        assert_eq!(eval("$<BOOL:Foo-NOTFOUND>"), "0");
+        assert_eq!(eval("$<BOOL:>"), "0");
+        assert_eq!(eval("$<AND:1,$<BOOL:yes>>"), "1");
+        assert_eq!(eval("$<AND:1,0>"), "0");
+        assert_eq!(eval("$<OR:0,1>"), "1");
+        assert_eq!(eval("$<STREQUAL:a,a>"), "1");
+        assert_eq!(eval("$<EQUAL:10,010>"), "1");
+        assert_eq!(eval("$<EQUAL:a,b>"), "0");
+        assert_eq!(eval("$<EQUAL:1,b>"), "0");
+        assert_eq!(eval("$<IN_LIST:b,a$<SEMICOLON>b>"), "1");
+        assert_eq!(eval("$<VERSION_LESS:1.2,1.10>"), "1");
+        assert_eq!(
+            eval("$<VERSION_GREATER_EQUAL:$<CXX_COMPILER_VERSION>,14>"),
+            "1"
+        );
+    }
+
+    #[test]
+    fn toolchain() {
+        assert_eq!(eval("$<$<PLATFORM_ID:Windows>:ws2_32>"), "");
+        assert_eq!(eval("$<$<PLATFORM_ID:Linux,Darwin>:dl>"), "dl");
+        assert_eq!(eval("$<$<CXX_COMPILER_ID:GNU,Clang>:-Wall>"), "-Wall");
+        assert_eq!(eval("$<$<C_COMPILER_ID:MSVC>:/W4>"), "");
+        assert_eq!(eval("$<$<COMPILE_LANGUAGE:CXX>:-fno-rtti>"), "-fno-rtti");
+        assert_eq!(eval("$<$<COMPILE_LANGUAGE:Fortran>:-ffree-form>"), "");
+        assert_eq!(
+            eval("$<$<COMPILE_LANG_AND_ID:CXX,GNU>:-fconcepts>"),
+            "-fconcepts"
+        );
+        assert_eq!(eval("$<$<COMPILE_LANG_AND_ID:CXX,MSVC>:/permissive->"), "");
+    }
+
+    #[test]
+    fn compile_language() {
+        let context = context();
+        assert_eq!(
+            evaluate_compile_list("-Wall;$<$<CONFIG:Debug>:-O0>", &context, &mut Vec::new()),
+            Some(vec!["-Wall".to_string(), "-O0".to_string()])
+        );
+        assert_eq!(
+            evaluate_compile_list(
+                "$<$<COMPILE_LANGUAGE:C,CXX>:-fPIC>",
+                &context,
+                &mut Vec::new()
+            ),
+            Some(vec!["-fPIC".to_string()])
+        );
+        assert_eq!(
+            evaluate_compile_list(
+                "$<$<COMPILE_LANGUAGE:CXX>:-std=c++17>",
+                &context,
+                &mut Vec::new()
+            ),
+            None
+        );
+        assert_eq!(
+            evaluate_compile_list(
+                "$<$<COMPILE_LANG_AND_ID:C,GNU>:-std=gnu11>",
+                &context,
+                &mut Vec::new()
+            ),
+            None
+        );
+        assert_eq!(
+            evaluate_compile_list("-x$<COMPILE_LANGUAGE>", &context, &mut Vec::new()),
+            None
+        );
+        // Linking is the same for all the compiled languages
+        assert_eq!(
+            evaluate_compile_list("$<$<LINK_LANGUAGE:CXX>:stdc++>", &context, &mut Vec::new()),
+            Some(vec!["stdc++".to_string()])
+        );
+    }
+
+    #[test]
+    fn usage_requirements() {
+        let context = context();
+        assert_eq!(
+            evaluate(
+                "$<LINK_ONLY:Threads::Threads>",
+                &context,
+                true,
+                &mut Vec::new()
+            ),
+            "Threads::Threads"
+        );
+        assert_eq!(
+            evaluate(
+                "$<LINK_ONLY:Threads::Threads>",
+                &context,
+                false,
+                &mut Vec::new()
+            ),
+            ""
+        );
+        assert_eq!(
+            eval("$<BUILD_INTERFACE:/src/foo/include>"),
+            "/src/foo/include"
+        );
+        assert_eq!(eval("$<INSTALL_INTERFACE:include>"), "");
+        assert_eq!(eval("$<TARGET_NAME:Foo::Foo>"), "Foo::Foo");
+    }
+
+    #[test]
+    fn targets() {
+        let context = Context {
+            targets: HashSet::from(["Foo::Foo".to_string()]),
+            ..context()
+        };
+        let eval = |value| evaluate(value, &context, true, &mut Vec::new());
+        assert_eq!(eval("$<TARGET_EXISTS:Foo::Foo>"), "1");
+        assert_eq!(eval("$<TARGET_EXISTS:Foo::Bar>"), "0");
+        assert_eq!(eval("$<TARGET_NAME_IF_EXISTS:Foo::Foo>"), "Foo::Foo");
+        assert_eq!(eval("$<TARGET_NAME_IF_EXISTS:Foo::Bar>"), "");
+    }
+
+    #[test]
+    fn unsupported() {
+        let mut unsupported = Vec::new();
+        assert_eq!(
+            evaluate(
+                "-L$<TARGET_FILE_DIR:Foo::Foo>;$<JOIN:a;b,->;$<IF:1,x,$<GENEX_EVAL:y>>",
+                &context(),
+                false,
+                &mut unsupported
+            ),
+            "-L;;x"
+        );
+        assert_eq!(
+            unsupported,
+            ["$<TARGET_FILE_DIR:Foo::Foo>", "$<JOIN:a;b,->"]
+        );
+
+        // The branches not taken are not evaluated
+        let mut unsupported = Vec::new();
+        let value = "$<$<TARGET_EXISTS:Foo::Bar>:$<TARGET_PROPERTY:Foo::Bar,LOCATION>>";
+        assert_eq!(evaluate(value, &context(), false, &mut unsupported), "");
+        assert!(unsupported.is_empty());
+    }
+
+    #[test]
+    fn link_features() {
+        let context = context();
+        let value =
+            "$<LINK_GROUP:RESCAN,$<LINK_LIBRARY:WHOLE_ARCHIVE,Foo::plugin>,/usr/lib/libbar.a>";
+        let items = evaluate_list(value, &context, true, &mut Vec::new());
+        assert_eq!(
+            items,
+            vec![
+                "$<LINK_GROUP:RESCAN,$<LINK_LIBRARY:WHOLE_ARCHIVE,Foo::plugin>>",
+                "$<LINK_GROUP:RESCAN,/usr/lib/libbar.a>"
+            ]
+        );
+        assert_eq!(
+            evaluate_list(value, &context, false, &mut Vec::new()),
+            vec!["Foo::plugin", "/usr/lib/libbar.a"]
+        );
+
+        assert_eq!(
+            strip_link_features(&items[0]),
+            (
+                "Foo::plugin",
+                LinkFeatures {
+                    feature: Some("WHOLE_ARCHIVE".into()),
+                    group: Some("RESCAN".into())
+                }
+            )
+        );
+        assert_eq!(
+            strip_link_features(&items[1]),
+            (
+                "/usr/lib/libbar.a",
+                LinkFeatures {
+                    feature: None,
+                    group: Some("RESCAN".into())
+                }
+            )
+        );
+        assert_eq!(
+            strip_link_features("/usr/lib/libfoo.a"),
+            ("/usr/lib/libfoo.a", LinkFeatures::default())
+        );
+    }
+
+    #[test]
+    fn lists() {
+        let context = context();
+        assert_eq!(
+            evaluate_list(
+                "$<$<CONFIG:Debug>:A$<SEMICOLON>B>",
+                &context,
+                false,
+                &mut Vec::new()
+            ),
+            vec!["A", "B"]
+        );
+        assert_eq!(
+            evaluate_list(
+                "$<$<CONFIG:Release>:A$<SEMICOLON>B>",
+                &context,
+                false,
+                &mut Vec::new()
+            ),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            evaluate_list("$<1:a,b>", &context, false, &mut Vec::new()),
+            vec!["a,b".to_string()]
+        );
+        assert_eq!(eval("$<ANGLE-R>$<COMMA>$<QUOTE>"), ">,\"");
+    }
+
+    #[test]
+    fn malformed() {
+        assert_eq!(eval("$<1:unterminated"), "unterminated");
+        assert_eq!(eval("$<>"), "");
+    }
+}
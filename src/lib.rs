@@ -55,23 +55,80 @@
 //! CMake command again the same directory, but this time the `CMakeLists.txt` attempts to locate
 //! the specified CMake target and list all its (relevant) properties and properties of all its
 //! transitive dependencies. The result is again written into a JSON file that is then processed
-//! by the crate to produce the [`CMakeTarget`] instance.
+//! by the crate to produce the [`CMakeTarget`] instance. Use [`CMakePackage::targets()`] to
+//! resolve multiple targets in a single CMake run.
+//!
+//! Once the package is found, the crate prints `cargo:rerun-if-changed` directives for the
+//! package configuration files and `cargo:rerun-if-env-changed` directives for the environment
+//! variables that affect the search (see [`CMakePackage::config_paths()`]). Note that once a build
+//! script prints any of these directives, Cargo no longer reruns it when any file in the package
+//! changes, so you may want to print `cargo:rerun-if-changed=build.rs` from your build script as well.
+//!
+//! # Cross-Compilation
+//!
+//! When cross-compiling, CMake must search for the packages built for the target rather than
+//! for the host. Specify a [toolchain file][cmake_toolchains] for the target with
+//! [`FindPackageBuilder::toolchain_file()`] or with the `CMAKE_TOOLCHAIN_FILE_<target>` environment
+//! variable. If there's none, the crate generates a simple toolchain file with `CMAKE_SYSTEM_NAME`
+//! and `CMAKE_SYSTEM_PROCESSOR` derived from the Cargo target. The `CMAKE_SYSROOT` environment
+//! variable (or `CMAKE_SYSROOT_<target>`) specifies the sysroot of the target, which is searched for
+//! the packages together with the multiarch directory of the target (e.g. `/usr/aarch64-linux-gnu`),
+//! if it exists.
+//!
+//! # Caching
+//!
+//! Running CMake is slow, so the results are cached in the `OUT_DIR` of the build script and
+//! reused on subsequent runs of the build script. A cached result is used only if the `cmake`
+//! executable, all the options of the search and the environment variables that affect it are
+//! the same, and none of the package configuration files or the libraries has been modified since.
+//! Packages that were not found, or were found by a find module (`Find<Package>.cmake`) rather
+//! than a package configuration file, are never cached.
+//!
+//! Set the `CMAKE_PACKAGE_CACHE_DIR` environment variable or use [`FindPackageBuilder::cache_dir()`]
+//! to store the cache elsewhere, e.g. in a directory shared by all build scripts in the workspace.
+//! Use [`FindPackageBuilder::no_cache()`] to disable the cache.
+//!
+//! # Lock Files
+//!
+//! Some build environments (e.g. [docs.rs][docs_rs] or minimal CI containers) don't have CMake
+//! installed. To build in those, record the packages in an environment with CMake by setting
+//! the `CMAKE_PACKAGE_RECORD` environment variable to a path of a lock file. Every package found
+//! and every target and executable resolved is written into the lock file. With the
+//! `CMAKE_PACKAGE_REPLAY` environment variable set to the path of the lock file, the packages,
+//! targets and executables are read from the lock file instead and CMake is never run. Packages
+//! and targets not present in the lock file are reported as not found.
+//!
+//! A package is recorded separately for each build type and set of options it was found with
+//! (prefix paths, cache variables, languages and toolchain file). To replay both debug and
+//! release builds, record both of them. Build scripts running in parallel can record into the
+//! same lock file.
+//!
+//! Note that the lock file contains absolute paths to the libraries, so the libraries must be
+//! installed in the same locations when replaying.
 //!
 //! # Known Limitations
 //!
-//! The crate currently supporst primarily linking against shared libraries. Linking against
-//! static libraries is not tested and may not work as expected. The crate currently does not
-//! support linking against MacOS frameworks.
+//! The crate supports linking against shared and static libraries and Apple frameworks. Apple
+//! linkers can't link a library by its exact file name, so shared libraries with names that
+//! `-l<name>` would not resolve to (e.g. `libfoo.1.dylib` without the `libfoo.dylib` symlink) are
+//! passed to the linker by their full path as linker arguments, which don't propagate to the
+//! crates depending on the crate that links them.
 //!
-//! [CMake generator expressions][cmake_generator_expr] are not supported in property values
-//! right now, because they are evaluated at later stage of the build, not during the "configure"
-//! phase of CMake, which is what this crate does. Some generator expressions could be supported
-//! by the crate in the future (e.g. by evaluating them ourselves).
+//! [CMake generator expressions][cmake_generator_expr] are evaluated at later stage of the build,
+//! not during the "configure" phase of CMake, which is what this crate does. The crate therefore
+//! evaluates the generator expressions in property values itself. Only a subset of the expressions
+//! is supported (conditionals, logical operators, comparisons, build configuration, platform and
+//! compiler queries, `$<TARGET_EXISTS:...>` and `$<LINK_ONLY:...>`), the remaining expressions
+//! (e.g. `$<TARGET_PROPERTY:...>`) evaluate to an empty string and are reported with a
+//! `cargo:warning`.
 //!
-//! There's currently no way to customize the `CMakeLists.txt` file that is used to query the
-//! package or the target in order to extract non-standard properties or variables set by
-//! the CMake package. This may be addressed in the future.
+//! The `CMakeLists.txt` file that is used to query the package and the targets is embedded in the
+//! crate. It can be replaced with a custom script using [`FindPackageBuilder::script()`], but the
+//! crate only extracts the standard properties of the targets from its output, not any non-standard
+//! properties or variables set by the CMake package.
 //!
+//! [docs_rs]: https://docs.rs
+//! [cmake_toolchains]: https://cmake.org/cmake/help/latest/manual/cmake-toolchains.7.html
 //! [wiki_path]: https://en.wikipedia.org/wiki/PATH_(variable)
 //! [cmake_package]: https://cmake.org/cmake/help/latest/manual/cmake-packages.7.html
 //! [cmake_target]: https://cmake.org/cmake/help/latest/manual/cmake-buildsystem.7.html#target-build-specification
@@ -80,15 +137,26 @@
 //! [cmake_generator_expr]: https://cmake.org/cmake/help/latest/manual/cmake-generator-expressions.7.html
 
 use std::io::Write;
+use std::path::PathBuf;
+use std::process::Command;
 
-#[cfg(target_os = "linux")]
-use regex::Regex;
-use tempfile::TempDir;
+use serde::{Deserialize, Serialize};
 
+mod cache;
 mod cmake;
+mod compiler;
+mod genex;
+mod link;
+mod lock;
+mod options;
+mod toolchain;
 mod version;
 
-pub use cmake::{find_cmake, CMakeProgram, Error, CMAKE_MIN_VERSION};
+use cmake::CMakeOptions;
+use link::{LinkItem, LinkKind, TargetPlatform};
+
+pub use cmake::{find_cmake, CMakeBuildType, CMakeProgram, Error, CMAKE_MIN_VERSION};
+pub use link::{LibraryKind, LinkLibrary, LinkPreference};
 pub use version::{Version, VersionError};
 
 /// A CMake package found on the system.
@@ -104,9 +172,10 @@ pub use version::{Version, VersionError};
 /// ```
 #[derive(Debug)]
 pub struct CMakePackage {
-    cmake: CMakeProgram,
-    working_directory: TempDir,
-    verbose: bool,
+    source: Source,
+    available_targets: Vec<String>,
+    config_paths: Vec<PathBuf>,
+    options: CMakeOptions,
 
     /// Name of the CMake package
     pub name: String,
@@ -117,31 +186,245 @@ pub struct CMakePackage {
 }
 
 impl CMakePackage {
+    #[allow(clippy::too_many_arguments)]
     fn new(
-        cmake: CMakeProgram,
-        working_directory: TempDir,
+        source: Source,
         name: String,
         version: Option<Version>,
         components: Option<Vec<String>>,
-        verbose: bool,
+        available_targets: Vec<String>,
+        config_paths: Vec<PathBuf>,
+        options: CMakeOptions,
     ) -> Self {
         Self {
-            cmake,
-            working_directory,
+            source,
+            available_targets,
+            config_paths,
+            options,
             name,
             version,
             components,
-            verbose,
         }
     }
 
+    /// Returns names of all [imported targets][cmake_target] defined by the package.
+    ///
+    /// This is useful when the exact name of the target exported by the package is not known
+    /// upfront (e.g. `Foo::Foo` vs. `Foo::foo`), so the build script can pick the right one at runtime.
+    /// The list also contains targets defined by dependencies of the package.
+    ///
+    /// The list is obtained from the [`IMPORTED_TARGETS`][cmake_imported_targets] directory property,
+    /// which requires CMake 3.21 or newer. With older versions of CMake the list is always empty.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use cmake_package::find_package;
+    ///
+    /// let package = find_package("Foo").find().unwrap();
+    /// let target = ["Foo::Foo", "Foo::foo"]
+    ///     .into_iter()
+    ///     .find(|name| package.available_targets().iter().any(|target| target == name))
+    ///     .and_then(|name| package.target(name));
+    /// ```
+    ///
+    /// [cmake_target]: https://cmake.org/cmake/help/latest/manual/cmake-buildsystem.7.html#imported-targets
+    /// [cmake_imported_targets]: https://cmake.org/cmake/help/latest/prop_dir/IMPORTED_TARGETS.html
+    pub fn available_targets(&self) -> &[String] {
+        &self.available_targets
+    }
+
+    /// Returns paths to the package configuration files and directories that were used to find
+    /// the package.
+    ///
+    /// The list contains the package configuration file of the package and of all its dependencies
+    /// (the `<PackageName>_CONFIG` variables), all `*Targets*.cmake` files next to them and the
+    /// directory containing the package configuration file (`<PackageName>_DIR`). It is empty
+    /// for packages found by a find module.
+    ///
+    /// When the package is found, [`cargo:rerun-if-changed`][cargo_rerun_if_changed] is printed for
+    /// each of the paths, together with [`cargo:rerun-if-env-changed`][cargo_rerun_if_env_changed]
+    /// for the environment variables that affect the search, so that the build script is rerun when
+    /// the package is upgraded or reinstalled.
+    ///
+    /// [cargo_rerun_if_changed]: https://doc.rust-lang.org/cargo/reference/build-scripts.html#rerun-if-changed
+    /// [cargo_rerun_if_env_changed]: https://doc.rust-lang.org/cargo/reference/build-scripts.html#rerun-if-env-changed
+    pub fn config_paths(&self) -> &[PathBuf] {
+        &self.config_paths
+    }
+
+    fn rerun_if_changed_write<W: Write>(&self, io: &mut W) {
+        self.config_paths.iter().for_each(|path| {
+            writeln!(io, "cargo:rerun-if-changed={}", path.display()).unwrap();
+        });
+        cmake::tracked_env_vars(&self.name).iter().for_each(|var| {
+            writeln!(io, "cargo:rerun-if-env-changed={}", var).unwrap();
+        });
+    }
+
     /// Queries the CMake package for information about a specific [CMake target][cmake_target].
-    /// Returns `None` if the target is not found in the package.
+    /// Returns `None` if the target is not found in the package or if an error occurs. Use
+    /// [`try_target()`][Self::try_target] to find out what went wrong.
     ///
     /// [cmake_target]: https://cmake.org/cmake/help/latest/manual/cmake-buildsystem.7.html#imported-targets
     pub fn target(&self, target: impl Into<String>) -> Option<CMakeTarget> {
-        cmake::find_target(self, target)
+        self.try_target(target).ok()
+    }
+
+    /// Queries the CMake package for information about a specific [CMake target][cmake_target].
+    ///
+    /// Returns [`Error::TargetNotFound`] if the target does not exist in the package, or another
+    /// [`Error`] if running CMake or processing its output fails.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use cmake_package::{find_package, Error};
+    ///
+    /// let package = find_package("OpenSSL").find().unwrap();
+    /// match package.try_target("OpenSSL::SSL") {
+    ///     Ok(target) => target.link(),
+    ///     Err(Error::TargetNotFound { available, .. }) => {
+    ///         panic!("OpenSSL::SSL not found, available targets: {:?}", available)
+    ///     }
+    ///     Err(err) => panic!("Failed to resolve OpenSSL::SSL: {}", err),
+    /// }
+    /// ```
+    ///
+    /// [cmake_target]: https://cmake.org/cmake/help/latest/manual/cmake-buildsystem.7.html#imported-targets
+    pub fn try_target(&self, target: impl Into<String>) -> Result<CMakeTarget, Error> {
+        let target = target.into();
+        self.find_targets(&[&target])?
+            .pop()
+            .flatten()
+            .ok_or_else(|| self.target_not_found(target))
+    }
+
+    /// Queries the CMake package for information about multiple [CMake targets][cmake_target] at once.
+    ///
+    /// All the targets are resolved in a single CMake run, which is considerably faster than calling
+    /// [`target()`][Self::target] for each of the targets. The returned vector has the same length and
+    /// order as `targets`, with `None` for each target that was not found in the package. If an error
+    /// occurs, `None` is returned for all targets. Use [`try_targets()`][Self::try_targets] to find out
+    /// what went wrong.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use cmake_package::find_package;
+    ///
+    /// let package = find_package("Qt6").components(["Core".into(), "Gui".into()]).find().unwrap();
+    /// let targets = package.targets(&["Qt6::Core", "Qt6::Gui"]);
+    /// for target in targets.into_iter().flatten() {
+    ///     target.link();
+    /// }
+    /// ```
+    ///
+    /// [cmake_target]: https://cmake.org/cmake/help/latest/manual/cmake-buildsystem.7.html#imported-targets
+    pub fn targets(&self, targets: &[impl AsRef<str>]) -> Vec<Option<CMakeTarget>> {
+        self.find_targets(targets)
+            .unwrap_or_else(|_| vec![None; targets.len()])
+    }
+
+    /// Queries the CMake package for information about multiple [CMake targets][cmake_target] at once.
+    ///
+    /// Same as [`targets()`][Self::targets], but returns [`Error::TargetNotFound`] for the first target that
+    /// does not exist in the package, or another [`Error`] if running CMake or processing its output fails.
+    ///
+    /// [cmake_target]: https://cmake.org/cmake/help/latest/manual/cmake-buildsystem.7.html#imported-targets
+    pub fn try_targets(&self, targets: &[impl AsRef<str>]) -> Result<Vec<CMakeTarget>, Error> {
+        self.find_targets(targets)?
+            .into_iter()
+            .zip(targets)
+            .map(|(result, target)| result.ok_or_else(|| self.target_not_found(target.as_ref())))
+            .collect()
+    }
+
+    /// Queries the CMake package for an imported executable target, such as a code generator or
+    /// another tool provided by the package (e.g. `Qt6::moc` or `protobuf::protoc`).
+    ///
+    /// Returns [`Error::TargetNotFound`] if the target does not exist in the package,
+    /// [`Error::NotAnExecutable`] if the target is not an executable and [`Error::LocationNotFound`]
+    /// if the package does not provide the executable for the current build type.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use cmake_package::find_package;
+    ///
+    /// let package = find_package("Qt6").components(["Core".into()]).find().unwrap();
+    /// let moc = package.executable("Qt6::moc").unwrap();
+    /// let status = moc.command().arg("--version").status().unwrap();
+    /// assert!(status.success());
+    /// ```
+    pub fn executable(&self, name: impl Into<String>) -> Result<CMakeExecutable, Error> {
+        let name = name.into();
+        let executable = match &self.source {
+            Source::CMake(project) => cmake::find_executable(self, project, &name)?,
+            Source::Replay(locked) => locked.executables.get(&name).cloned(),
+        };
+        if let Some(executable) = &executable {
+            lock::record(self, |locked| {
+                locked.executables.insert(name.clone(), executable.clone());
+            })?;
+        }
+        executable.ok_or_else(|| self.target_not_found(name))
+    }
+
+    fn find_targets(&self, targets: &[impl AsRef<str>]) -> Result<Vec<Option<CMakeTarget>>, Error> {
+        let result = match &self.source {
+            Source::CMake(project) => cmake::find_targets(self, project, targets)?,
+            Source::Replay(locked) => targets
+                .iter()
+                .map(|target| locked.targets.get(target.as_ref()).cloned())
+                .collect(),
+        };
+        lock::record(self, |locked| {
+            locked.targets.extend(
+                targets.iter().zip(&result).filter_map(|(name, target)| {
+                    Some((name.as_ref().to_string(), target.clone()?))
+                }),
+            );
+        })?;
+        Ok(result)
     }
+
+    fn target_not_found(&self, target: impl Into<String>) -> Error {
+        Error::TargetNotFound {
+            package: self.name.clone(),
+            target: target.into(),
+            available: self.available_targets.clone(),
+        }
+    }
+}
+
+/// Where the targets of a [`CMakePackage`] are resolved from.
+#[derive(Debug)]
+enum Source {
+    /// The targets are resolved by running CMake in the project that found the package.
+    CMake(cmake::Project),
+    /// The targets are read from a lock file (see [Lock Files](crate#lock-files)).
+    Replay(lock::LockedPackage),
+}
+
+/// Type of an imported CMake target, as reported by its [`TYPE`][cmake_type] property.
+///
+/// [cmake_type]: https://cmake.org/cmake/help/latest/prop_tgt/TYPE.html
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum TargetType {
+    /// A static library (`STATIC_LIBRARY`)
+    StaticLibrary,
+    /// A shared library (`SHARED_LIBRARY`)
+    SharedLibrary,
+    /// A module library that is not linked against, but loaded at runtime (`MODULE_LIBRARY`)
+    ModuleLibrary,
+    /// A library without any binary, e.g. a header-only library (`INTERFACE_LIBRARY`)
+    InterfaceLibrary,
+    /// A collection of object files (`OBJECT_LIBRARY`)
+    ObjectLibrary,
+    /// An executable (`EXECUTABLE`)
+    Executable,
+    /// A library of an unknown kind, typically created by find modules (`UNKNOWN_LIBRARY`)
+    #[default]
+    #[serde(other)]
+    UnknownLibrary,
 }
 
 /// Describes a CMake target found in a CMake package.
@@ -165,10 +448,13 @@ impl CMakePackage {
 /// ```
 ///
 /// [cc_crate]: https://crates.io/crates/cc
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(default)]
 pub struct CMakeTarget {
     /// Name of the CMake target
     pub name: String,
+    /// Type of the CMake target
+    pub target_type: TargetType,
     /// List of public compile definitions requirements for a library.
     ///
     /// Contains preprocessor definitions provided by the target and all its transitive dependencies
@@ -180,6 +466,7 @@ pub struct CMakeTarget {
     ///
     /// Contains compiler options provided by the target and all its transitive dependencies via
     /// their [`INTERFACE_COMPILE_OPTIONS`][cmake_interface_compile_options] target properties.
+    /// The `SHELL:` option groups are split into separate options.
     ///
     /// [cmake_interface_compile_options]: https://cmake.org/cmake/help/latest/prop_tgt/INTERFACE_COMPILE_OPTIONS.html
     pub compile_options: Vec<String>,
@@ -200,31 +487,22 @@ pub struct CMakeTarget {
     /// List of target's direct link dependencies, followed by indirect dependencies from the transitive closure of the direct
     /// dependencies' [`INTERFACE_LINK_LIBRARIES`][cmake_interface_link_libraries] properties
     ///
+    /// The list is deduplicated and ordered topologically, i.e. each library comes before all the
+    /// libraries it depends on, so it can be passed to the linker as it is.
+    ///
     /// [cmake_interface_link_libraries]: https://cmake.org/cmake/help/latest/prop_tgt/INTERFACE_LINK_LIBRARIES.html
-    pub link_libraries: Vec<String>,
+    pub link_libraries: Vec<LinkLibrary>,
     /// List of options to use for the link step of shared library, module and executable targets as well as the device link step.
     ///
     /// Contains link options provided by the target and all its transitive dependencies via
     /// their [`INTERFACE_LINK_OPTIONS`][cmake_interface_link_options] target properties.
+    /// The `SHELL:` option groups are split into separate options, and the `LINKER:` options are
+    /// translated into options for the compiler driver, e.g. `LINKER:-z,defs` into `-Wl,-z,defs`.
     ///
     /// [cmake_interface_link_options]: https://cmake.org/cmake/help/latest/prop_tgt/INTERFACE_LINK_OPTIONS.html
     pub link_options: Vec<String>,
 }
 
-/// Turns /usr/lib/libfoo.so.5 into foo, so that -lfoo rather than -l/usr/lib/libfoo.so.5
-/// is passed to the linker.
-#[cfg(target_os = "linux")]
-fn link_name(lib: &str) -> Option<&str> {
-    let regex = Regex::new(r"lib([^/]+)\.so.*").ok()?;
-    regex.captures(lib)?.get(1).map(|f| f.as_str())
-}
-
-
-#[cfg(target_os = "windows")]
-fn link_name(lib: &str) -> Option<&str> {
-    Some(lib)
-}
-
 impl CMakeTarget {
     /// Instructs cargo to link the final binary against the target.
     ///
@@ -232,27 +510,163 @@ impl CMakeTarget {
     /// [`cargo:rustc-link-arg={}`][cargo_rustc_link_arg], and [`cargo:rustc-link-lib=dylib={}`][cargo_rustc_link_lib]
     /// directives to the standard output for each of the target's [`link_directories`][Self::link_directories],
     /// [`link_options`][Self::link_options], and [`link_libraries`][Self::link_libraries] respectively.
+    /// Static libraries are linked with `cargo:rustc-link-lib=static={}`. The directory containing
+    /// a static or shared library is added to the search path. `INTERFACE` libraries don't contribute any library
+    /// of their own, only the usage requirements of their dependencies.
+    ///
+    /// The library names are derived for the Cargo target (`CARGO_CFG_TARGET_OS`, `CARGO_CFG_TARGET_ENV`
+    /// and `CARGO_CFG_TARGET_VENDOR`) rather than for the host the build script runs on. Frameworks
+    /// on Apple platforms are linked with `cargo:rustc-link-lib=framework={}`. Shared libraries that
+    /// `-l<name>` would not resolve to (e.g. `libfoo.so.5` without the `libfoo.so` symlink) are linked
+    /// by their exact file name with `cargo:rustc-link-lib=dylib:+verbatim={}`, and the directory
+    /// containing the library is added to the search path. Raw linker items in the link libraries
+    /// are passed on as well: `-L<dir>` as a search path, `-l<name>` and bare names like `m` as
+    /// libraries, `-framework <name>` as a framework, and other flags as linker arguments.
+    ///
+    /// Static libraries with the `WHOLE_ARCHIVE` [feature][LinkLibrary::feature] are linked with
+    /// `cargo:rustc-link-lib=static:+whole-archive={}`. Libraries in a `RESCAN` [group][LinkLibrary::group]
+    /// are passed as linker arguments between `-Wl,--start-group` and `-Wl,--end-group`, except for
+    /// the Apple and MSVC linkers, which don't need that. rustc passes the linker arguments after
+    /// all the libraries, so the libraries following the group are passed as linker arguments as
+    /// well. Unlike libraries, linker arguments only apply to the binaries of the crate calling
+    /// this method and not to the crates depending on it, which is reported with a warning.
     ///
     /// [cargo_rustc_link_search]: https://doc.rust-lang.org/cargo/reference/build-scripts.html#rustc-link-search
     /// [cargo_rustc_link_arg]: https://doc.rust-lang.org/cargo/reference/build-scripts.html#rustc-link-arg
     /// [cargo_rustc_link_lib]: https://doc.rust-lang.org/cargo/reference/build-scripts.html#rustc-link-lib]
     pub fn link(&self) {
-        self.link_write(&mut std::io::stdout());
+        self.link_write(&mut std::io::stdout(), &TargetPlatform::from_env());
     }
 
-    fn link_write<W: Write>(&self, io: &mut W) {
+    fn link_write<W: Write>(&self, io: &mut W, platform: &TargetPlatform) {
         self.link_directories.iter().for_each(|dir| {
             writeln!(io, "cargo:rustc-link-search=native={}", dir).unwrap();
         });
         self.link_options.iter().for_each(|opt| {
             writeln!(io, "cargo:rustc-link-arg={}", opt).unwrap();
         });
-        self.link_libraries.iter().for_each(|lib| {
-            match link_name(lib) {
-                Some(lib) => writeln!(io, "cargo:rustc-link-lib=dylib={}", lib).unwrap(),
-                None => writeln!(io, "cargo:rustc-link-arg={}", lib).unwrap(),
+        let mut search_paths = self.link_directories.clone();
+        let mut framework_paths = Vec::new();
+        let mut search = |io: &mut W, framework: bool, dir: String| {
+            let (kind, paths) = if framework {
+                ("framework", &mut framework_paths)
+            } else {
+                ("native", &mut search_paths)
+            };
+            if !paths.contains(&dir) {
+                writeln!(io, "cargo:rustc-link-search={}={}", kind, dir).unwrap();
+                paths.push(dir);
             }
-        });
+        };
+        let link_lib = |io: &mut W, kind: LinkKind, name: &str, library: &LinkLibrary| {
+            let whole_archive = library.feature.as_deref() == Some("WHOLE_ARCHIVE");
+            let kind = match kind {
+                LinkKind::Static if whole_archive => "static:+whole-archive",
+                LinkKind::Static => "static",
+                LinkKind::Dylib => "dylib",
+                LinkKind::Verbatim => "dylib:+verbatim",
+                LinkKind::Framework => "framework",
+            };
+            writeln!(io, "cargo:rustc-link-lib={}={}", kind, name).unwrap();
+        };
+        // rustc passes the linker arguments after all the libraries, so once a group is started,
+        // the group and all the libraries after it are passed as linker arguments to keep their order
+        let mut as_args = false;
+        let mut group = None;
+        for (library, item) in platform.classify(&self.link_libraries) {
+            let library_group = library
+                .group
+                .as_deref()
+                .filter(|group| *group == "RESCAN" && platform.needs_link_groups());
+            if library_group != group {
+                if group.is_some() {
+                    writeln!(io, "cargo:rustc-link-arg=-Wl,--end-group").unwrap();
+                }
+                if library_group.is_some() {
+                    if !as_args {
+                        writeln!(
+                            io,
+                            "cargo:warning=Target {} links a group of libraries, which is passed to the linker as arguments and is not linked into dependent crates",
+                            self.name
+                        )
+                        .unwrap();
+                        as_args = true;
+                    }
+                    writeln!(io, "cargo:rustc-link-arg=-Wl,--start-group").unwrap();
+                }
+                group = library_group;
+            }
+            let arg = match item {
+                _ if !as_args => None,
+                LinkItem::File(link_name) => {
+                    Some((link_name.kind, library.item.trim().to_string()))
+                }
+                LinkItem::Name(LinkKind::Verbatim, name) => {
+                    Some((LinkKind::Verbatim, format!("-l:{}", name)))
+                }
+                LinkItem::Name(LinkKind::Static, name) => {
+                    Some((LinkKind::Static, format!("-l:lib{}.a", name)))
+                }
+                LinkItem::Name(LinkKind::Dylib, name) => {
+                    Some((LinkKind::Dylib, format!("-l{}", name)))
+                }
+                _ => None,
+            };
+            if let Some((kind, arg)) = arg {
+                let whole_archive =
+                    kind == LinkKind::Static && library.feature.as_deref() == Some("WHOLE_ARCHIVE");
+                if whole_archive {
+                    writeln!(io, "cargo:rustc-link-arg=-Wl,--whole-archive").unwrap();
+                }
+                writeln!(io, "cargo:rustc-link-arg={}", arg).unwrap();
+                if whole_archive {
+                    writeln!(io, "cargo:rustc-link-arg=-Wl,--no-whole-archive").unwrap();
+                }
+                continue;
+            }
+
+            match item {
+                LinkItem::File(link_name) => {
+                    let dir = link_name.dir.to_string_lossy().to_string();
+                    search(io, link_name.kind == LinkKind::Framework, dir);
+                    link_lib(io, link_name.kind, link_name.name, library);
+                }
+                LinkItem::Name(kind, name) => link_lib(io, kind, name, library),
+                LinkItem::SearchPath { framework, dir } => search(io, framework, dir.to_string()),
+                LinkItem::Arg(arg) => writeln!(io, "cargo:rustc-link-arg={}", arg).unwrap(),
+                LinkItem::Target(target) => writeln!(
+                    io,
+                    "cargo:warning=Target {} links against {}, which is not a known CMake target",
+                    self.name, target
+                )
+                .unwrap(),
+            }
+        }
+        if group.is_some() {
+            writeln!(io, "cargo:rustc-link-arg=-Wl,--end-group").unwrap();
+        }
+    }
+}
+
+/// Describes an imported executable target found in a CMake package.
+///
+/// The executable can be obtained by calling the [`executable()`][CMakePackage::executable()] method
+/// on a [`CMakePackage`] instance.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CMakeExecutable {
+    /// Name of the CMake target
+    pub name: String,
+    /// Path to the executable for the current build type, as given by the
+    /// [`IMPORTED_LOCATION`][cmake_imported_location] target property.
+    ///
+    /// [cmake_imported_location]: https://cmake.org/cmake/help/latest/prop_tgt/IMPORTED_LOCATION.html
+    pub location: PathBuf,
+}
+
+impl CMakeExecutable {
+    /// Creates a [`Command`] to run the executable.
+    pub fn command(&self) -> Command {
+        Command::new(&self.location)
     }
 }
 
@@ -265,7 +679,7 @@ pub struct FindPackageBuilder {
     name: String,
     version: Option<Version>,
     components: Option<Vec<String>>,
-    verbose: bool,
+    options: CMakeOptions,
 }
 
 impl FindPackageBuilder {
@@ -274,7 +688,7 @@ impl FindPackageBuilder {
             name,
             version: None,
             components: None,
-            verbose: false,
+            options: CMakeOptions::default(),
         }
     }
 
@@ -309,17 +723,206 @@ impl FindPackageBuilder {
     /// Enable verbose output.
     /// This will redirect output from actual execution of the `cmake` command to the standard output
     /// and standard error of the build script.
-    pub fn verbose(self) -> Self {
-        Self {
-            verbose: true,
-            ..self
-        }
+    pub fn verbose(mut self) -> Self {
+        self.options.verbose = true;
+        self
+    }
+
+    /// Optionally specifies additional installation prefixes to search for the package.
+    ///
+    /// The paths are passed to CMake as the [`CMAKE_PREFIX_PATH`][cmake_prefix_path] variable, which
+    /// is searched in addition to the `CMAKE_PREFIX_PATH` environment variable. This is useful for
+    /// packages installed into non-standard locations.
+    ///
+    /// [cmake_prefix_path]: https://cmake.org/cmake/help/latest/variable/CMAKE_PREFIX_PATH.html
+    pub fn prefix_paths<P: Into<PathBuf>>(mut self, paths: impl IntoIterator<Item = P>) -> Self {
+        self.options.prefix_paths = paths.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Optionally specifies the directory containing the package configuration file
+    /// (e.g. `FooConfig.cmake` or `foo-config.cmake`).
+    ///
+    /// The path is passed to CMake as the `<PackageName>_DIR` variable. See the documentation
+    /// on CMake's [`find_package()`][cmake_find_package] function for details.
+    ///
+    /// [cmake_find_package]: https://cmake.org/cmake/help/latest/command/find_package.html#config-mode-search-procedure
+    pub fn package_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.options.package_dir = Some(dir.into());
+        self
+    }
+
+    /// Optionally specifies the installation prefix of the package.
+    ///
+    /// The path is passed to CMake as the [`<PackageName>_ROOT`][cmake_package_root] variable, which
+    /// is searched first by both the package configuration files and the find modules.
+    ///
+    /// [cmake_package_root]: https://cmake.org/cmake/help/latest/variable/PackageName_ROOT.html
+    pub fn package_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.options.package_root = Some(root.into());
+        self
+    }
+
+    /// Defines a CMake cache variable for the package search.
+    ///
+    /// The variable is passed as `-D<key>=<value>` to every CMake invocation made for the package,
+    /// including the ones made by [`CMakePackage::target()`]. This is useful to tune the behavior of
+    /// find modules and package configuration files with hint variables, such as `OPENSSL_USE_STATIC_LIBS`
+    /// or `QT_NO_CREATE_VERSIONLESS_TARGETS`. The method can be called repeatedly to define multiple
+    /// variables.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use cmake_package::find_package;
+    ///
+    /// let package = find_package("OpenSSL")
+    ///     .define("OPENSSL_USE_STATIC_LIBS", "ON")
+    ///     .find()
+    ///     .unwrap();
+    /// ```
+    pub fn define(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.options.defines.push((key.into(), value.into()));
+        self
+    }
+
+    /// Optionally specifies whether to prefer static or shared libraries when the package provides
+    /// both variants of a library.
+    ///
+    /// When a library chosen by the package has a variant of the preferred kind with the same name
+    /// in the same directory (e.g. `libfoo.a` next to `libfoo.so`), the variant is linked instead.
+    /// Many find modules select the variant themselves based on a hint variable, which can be set
+    /// with [`define()`][Self::define] (e.g. `OPENSSL_USE_STATIC_LIBS`).
+    ///
+    /// # Example
+    /// ```no_run
+    /// use cmake_package::{find_package, LinkPreference};
+    ///
+    /// let package = find_package("ZLIB")
+    ///     .link_preference(LinkPreference::Static)
+    ///     .find()
+    ///     .unwrap();
+    /// ```
+    pub fn link_preference(mut self, preference: LinkPreference) -> Self {
+        self.options.link_preference = preference;
+        self
+    }
+
+    /// Optionally specifies the CMake build type (configuration) of the package to use.
+    ///
+    /// By default the build type is derived from the Cargo profile (see [`CMakeBuildType`]). This is
+    /// useful e.g. to link against release builds of the package even in debug builds, or to select
+    /// a custom configuration provided by the package. The `CMAKE_PACKAGE_BUILD_TYPE` environment
+    /// variable takes precedence over this option.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use cmake_package::{find_package, CMakeBuildType};
+    ///
+    /// let package = find_package("Qt6")
+    ///     .build_type(CMakeBuildType::Release)
+    ///     .find()
+    ///     .unwrap();
+    /// let package = find_package("Foo").build_type("RelWithAssert").find().unwrap();
+    /// ```
+    pub fn build_type(mut self, build_type: impl Into<CMakeBuildType>) -> Self {
+        self.options.build_type = Some(build_type.into());
+        self
+    }
+
+    /// Optionally specifies the directory to cache the results of CMake runs in.
+    ///
+    /// By default the results are cached in the `OUT_DIR` of the build script, or in the directory
+    /// given by the `CMAKE_PACKAGE_CACHE_DIR` environment variable. Pointing multiple build scripts
+    /// to the same directory (e.g. inside the target directory) allows them to share the results.
+    /// See the [crate documentation](crate#caching) for details.
+    pub fn cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.options.cache_dir = Some(dir.into());
+        self
+    }
+
+    /// Disables caching of the results of CMake runs, CMake is run every time the package or
+    /// its targets are resolved.
+    pub fn no_cache(mut self) -> Self {
+        self.options.no_cache = true;
+        self
+    }
+
+    /// Optionally specifies the languages to enable in the CMake project used to find the package.
+    ///
+    /// The names are the CMake names of the languages, such as `C`, `CXX`, `OBJC` or `Fortran`, and
+    /// CMake requires a working compiler for each of them. An empty list enables no language at all
+    /// (`LANGUAGES NONE`), which is enough for packages that only ship prebuilt libraries.
+    ///
+    /// By default, only the C language is enabled, and only if a C compiler is available, since it's
+    /// required by the commonly used `FindThreads` module. When a compiler is needed, but not found,
+    /// [`Error::CompilerNotFound`] is returned.
+    ///
+    /// Generator expressions in the targets' properties that depend on the C++ compiler, such as
+    /// `$<CXX_COMPILER_ID:GNU>` or `$<COMPILE_LANG_AND_ID:CXX,MSVC>`, are only evaluated correctly
+    /// when the `CXX` language is enabled, e.g. with `languages(["C", "CXX"])`. Otherwise the C++
+    /// compiler is unknown and the expressions evaluate as if it didn't match.
+    ///
+    /// The C and C++ compilers and their flags are taken from the same environment variables as the
+    /// [cc][cc_crate] crate uses (e.g. `CC_<target>`, `TARGET_CC`, `CC` and `CFLAGS`), so that the
+    /// compilers used by CMake match the ones used to build the crate.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use cmake_package::find_package;
+    ///
+    /// let package = find_package("Foo").languages(["CXX"]).find().unwrap();
+    /// let package = find_package("Bar").languages(Vec::<String>::new()).find().unwrap();
+    /// ```
+    ///
+    /// [cc_crate]: https://docs.rs/cc/latest/cc/#external-configuration-via-environment-variables
+    pub fn languages(mut self, languages: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.options.languages = Some(languages.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Optionally specifies the [CMake toolchain file][cmake_toolchains] to use to find the package.
+    ///
+    /// When no toolchain file is specified, it is taken from the `CMAKE_TOOLCHAIN_FILE_<target>`,
+    /// `TARGET_CMAKE_TOOLCHAIN_FILE` or `CMAKE_TOOLCHAIN_FILE` environment variables, the same way
+    /// as the [cmake][cmake_crate] crate does. See [Cross-Compilation](crate#cross-compilation)
+    /// for what happens when no toolchain file is given at all. A relative path is relative to the
+    /// current directory of the build script, i.e. the directory of the crate's manifest.
+    ///
+    /// [cmake_toolchains]: https://cmake.org/cmake/help/latest/manual/cmake-toolchains.7.html
+    /// [cmake_crate]: https://docs.rs/cmake/latest/cmake/
+    pub fn toolchain_file(mut self, toolchain_file: impl Into<PathBuf>) -> Self {
+        self.options.toolchain_file = Some(toolchain_file.into());
+        self
+    }
+
+    /// Optionally specifies a custom CMake script to use instead of the built-in one.
+    ///
+    /// The script is copied into the working directory as `CMakeLists.txt` and is used both to find
+    /// the package and to resolve its targets. It must accept the same parameters and write the same
+    /// JSON output as the [built-in script][find_package_script], so it's best to start with a copy of
+    /// the built-in script and modify it, e.g. to set up variables before the package is searched for.
+    ///
+    /// [find_package_script]: https://github.com/danvratil/cmake-package-rs/blob/main/cmake/find_package.cmake
+    pub fn script(mut self, script: impl Into<PathBuf>) -> Self {
+        self.options.script = Some(script.into());
+        self
     }
 
     /// Tries to find the CMake package on the system.
     /// Returns a [`CMakePackage`] instance if the package is found, otherwise an error.
     pub fn find(self) -> Result<CMakePackage, cmake::Error> {
-        cmake::find_package(self.name, self.version, self.components, self.verbose)
+        let package = match lock::replay_path() {
+            Some(path) => lock::replay_package(
+                &path,
+                self.name,
+                self.version,
+                self.components,
+                self.options,
+            )?,
+            None => cmake::find_package(self.name, self.version, self.components, self.options)?,
+        };
+        lock::record(&package, |_| ())?;
+        Ok(package)
     }
 }
 
@@ -366,7 +969,44 @@ mod testing {
     }
 
     #[test]
-    #[cfg(target_os = "linux")]
+    fn test_rerun_if_changed() {
+        let package = CMakePackage::new(
+            Source::CMake(cmake::Project {
+                cmake: CMakeProgram {
+                    path: PathBuf::from("/usr/bin/cmake"),
+                    version: CMAKE_MIN_VERSION.try_into().unwrap(),
+                },
+                working_directory: tempfile::tempdir().unwrap(),
+            }),
+            "Foo".into(),
+            None,
+            None,
+            vec![],
+            vec![
+                PathBuf::from("/usr/lib/cmake/Foo/FooConfig.cmake"),
+                PathBuf::from("/usr/lib/cmake/Foo"),
+            ],
+            CMakeOptions::default(),
+        );
+
+        let mut buf = Vec::new();
+        package.rerun_if_changed_write(&mut buf);
+        let output = String::from_utf8(buf).unwrap();
+        let lines = output.lines().collect::<Vec<&str>>();
+        assert_eq!(
+            lines[..3],
+            [
+                "cargo:rerun-if-changed=/usr/lib/cmake/Foo/FooConfig.cmake",
+                "cargo:rerun-if-changed=/usr/lib/cmake/Foo",
+                "cargo:rerun-if-env-changed=CMAKE_PREFIX_PATH",
+            ]
+        );
+        assert!(lines.contains(&"cargo:rerun-if-env-changed=Foo_DIR"));
+        assert!(lines.contains(&"cargo:rerun-if-env-changed=FOO_ROOT"));
+        assert!(lines.contains(&"cargo:rerun-if-env-changed=CMAKE_PACKAGE_BUILD_TYPE"));
+    }
+
+    #[test]
     fn test_link_to() {
         let target = CMakeTarget {
             name: "foo".into(),
@@ -374,19 +1014,198 @@ mod testing {
             compile_options: vec![],
             include_directories: vec![],
             link_directories: vec!["/usr/lib64".into()],
-            link_libraries: vec!["/usr/lib/libbar.so".into(), "/usr/lib64/libfoo.so.5".into()],
+            target_type: TargetType::SharedLibrary,
+            link_libraries: vec![
+                LinkLibrary::new("/usr/lib/libbar.so"),
+                LinkLibrary::new("/usr/lib64/libfoo.so.5"),
+                LinkLibrary::new("-L/opt/baz/lib"),
+                LinkLibrary::new("-lbaz"),
+                LinkLibrary::new("-Wl,--as-needed"),
+                LinkLibrary::new("m"),
+            ],
             link_options: vec![],
         };
 
         let mut buf = Vec::new();
-        target.link_write(&mut buf);
+        target.link_write(&mut buf, &TargetPlatform::new("linux", "gnu", "unknown"));
         let output = String::from_utf8(buf).unwrap();
         assert_eq!(
             output.lines().collect::<Vec<&str>>(),
             vec![
                 "cargo:rustc-link-search=native=/usr/lib64",
+                "cargo:rustc-link-search=native=/usr/lib",
                 "cargo:rustc-link-lib=dylib=bar",
-                "cargo:rustc-link-lib=dylib=foo"
+                "cargo:rustc-link-lib=dylib:+verbatim=libfoo.so.5",
+                "cargo:rustc-link-search=native=/opt/baz/lib",
+                "cargo:rustc-link-lib=dylib=baz",
+                "cargo:rustc-link-arg=-Wl,--as-needed",
+                "cargo:rustc-link-lib=dylib=m",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_link_to_static() {
+        let target = CMakeTarget {
+            name: "foo".into(),
+            link_directories: vec!["/usr/lib64".into()],
+            link_libraries: vec![
+                LinkLibrary {
+                    item: "/opt/foo/lib/libfoo.a".into(),
+                    target: Some("Foo::Foo".into()),
+                    target_type: Some(TargetType::StaticLibrary),
+                    kind: LibraryKind::Static,
+                    ..Default::default()
+                },
+                LinkLibrary::new("/opt/foo/lib/libfoo_utils.a"),
+                LinkLibrary::new("/usr/lib64/libbar.a"),
+                LinkLibrary::new("/usr/lib/libz.so"),
+            ],
+            ..Default::default()
+        };
+
+        let mut buf = Vec::new();
+        target.link_write(&mut buf, &TargetPlatform::new("linux", "gnu", "unknown"));
+        let output = String::from_utf8(buf).unwrap();
+        assert_eq!(
+            output.lines().collect::<Vec<&str>>(),
+            vec![
+                "cargo:rustc-link-search=native=/usr/lib64",
+                "cargo:rustc-link-search=native=/opt/foo/lib",
+                "cargo:rustc-link-lib=static=foo",
+                "cargo:rustc-link-lib=static=foo_utils",
+                "cargo:rustc-link-lib=static=bar",
+                "cargo:rustc-link-search=native=/usr/lib",
+                "cargo:rustc-link-lib=dylib=z",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_link_to_apple() {
+        let target = CMakeTarget {
+            name: "foo".into(),
+            link_libraries: vec![
+                LinkLibrary::new("/opt/foo/lib/libfoo.1.dylib"),
+                LinkLibrary::new("/opt/Qt/lib/QtCore.framework/Versions/A/QtCore"),
+                LinkLibrary::new("/opt/Qt/lib/QtGui.framework/Versions/A/QtGui"),
+                LinkLibrary::new("/usr/lib/libz.tbd"),
+            ],
+            ..Default::default()
+        };
+
+        let mut buf = Vec::new();
+        target.link_write(&mut buf, &TargetPlatform::new("macos", "", "apple"));
+        let output = String::from_utf8(buf).unwrap();
+        assert_eq!(
+            output.lines().collect::<Vec<&str>>(),
+            vec![
+                "cargo:rustc-link-arg=/opt/foo/lib/libfoo.1.dylib",
+                "cargo:rustc-link-search=framework=/opt/Qt/lib",
+                "cargo:rustc-link-lib=framework=QtCore",
+                "cargo:rustc-link-lib=framework=QtGui",
+                "cargo:rustc-link-search=native=/usr/lib",
+                "cargo:rustc-link-lib=dylib=z",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_link_to_msvc() {
+        let target = CMakeTarget {
+            name: "foo".into(),
+            link_libraries: vec![
+                LinkLibrary {
+                    kind: LibraryKind::Dynamic,
+                    ..LinkLibrary::new("C:/Foo/lib/foo.lib")
+                },
+                LinkLibrary {
+                    kind: LibraryKind::Static,
+                    ..LinkLibrary::new("C:/Foo/lib/foo_static.lib")
+                },
+                LinkLibrary::new("ws2_32"),
+            ],
+            ..Default::default()
+        };
+
+        let mut buf = Vec::new();
+        target.link_write(&mut buf, &TargetPlatform::new("windows", "msvc", "pc"));
+        let output = String::from_utf8(buf).unwrap();
+        assert_eq!(
+            output.lines().collect::<Vec<&str>>(),
+            vec![
+                "cargo:rustc-link-search=native=C:/Foo/lib",
+                "cargo:rustc-link-lib=dylib=foo",
+                "cargo:rustc-link-lib=static=foo_static",
+                "cargo:rustc-link-lib=dylib=ws2_32",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_link_to_with_features() {
+        let target = CMakeTarget {
+            name: "foo".into(),
+            link_libraries: vec![
+                LinkLibrary {
+                    feature: Some("WHOLE_ARCHIVE".into()),
+                    ..LinkLibrary::new("/opt/foo/lib/libfoo_plugins.a")
+                },
+                LinkLibrary {
+                    group: Some("RESCAN".into()),
+                    ..LinkLibrary::new("/opt/foo/lib/liba.a")
+                },
+                LinkLibrary {
+                    group: Some("RESCAN".into()),
+                    ..LinkLibrary::new("-lb")
+                },
+                LinkLibrary {
+                    feature: Some("WHOLE_ARCHIVE".into()),
+                    group: Some("RESCAN".into()),
+                    ..LinkLibrary::new("/opt/foo/lib/libd.a")
+                },
+                LinkLibrary::new("/opt/foo/lib/libc.a"),
+                LinkLibrary::new("m"),
+            ],
+            ..Default::default()
+        };
+
+        let mut buf = Vec::new();
+        target.link_write(&mut buf, &TargetPlatform::new("linux", "gnu", "unknown"));
+        let output = String::from_utf8(buf).unwrap();
+        assert_eq!(
+            output.lines().collect::<Vec<&str>>(),
+            vec![
+                "cargo:rustc-link-search=native=/opt/foo/lib",
+                "cargo:rustc-link-lib=static:+whole-archive=foo_plugins",
+                "cargo:warning=Target foo links a group of libraries, which is passed to the linker as arguments and is not linked into dependent crates",
+                "cargo:rustc-link-arg=-Wl,--start-group",
+                "cargo:rustc-link-arg=/opt/foo/lib/liba.a",
+                "cargo:rustc-link-arg=-lb",
+                "cargo:rustc-link-arg=-Wl,--whole-archive",
+                "cargo:rustc-link-arg=/opt/foo/lib/libd.a",
+                "cargo:rustc-link-arg=-Wl,--no-whole-archive",
+                "cargo:rustc-link-arg=-Wl,--end-group",
+                // The libraries after the group must stay after it
+                "cargo:rustc-link-arg=/opt/foo/lib/libc.a",
+                "cargo:rustc-link-arg=-lm",
+            ]
+        );
+
+        // Apple linkers don't need the groups
+        let mut buf = Vec::new();
+        target.link_write(&mut buf, &TargetPlatform::new("macos", "", "apple"));
+        let output = String::from_utf8(buf).unwrap();
+        assert_eq!(
+            output.lines().collect::<Vec<&str>>(),
+            vec![
+                "cargo:rustc-link-search=native=/opt/foo/lib",
+                "cargo:rustc-link-lib=static:+whole-archive=foo_plugins",
+                "cargo:rustc-link-lib=static=a",
+                "cargo:rustc-link-lib=dylib=b",
+                "cargo:rustc-link-lib=static:+whole-archive=d",
+                "cargo:rustc-link-lib=static=c",
+                "cargo:rustc-link-lib=dylib=m",
             ]
         );
     }
@@ -0,0 +1,856 @@
+// SPDX-FileCopyrightText: 2024 Daniel Vrátil <dvratil@kde.org>
+//
+// SPDX-License-Identifier: MIT
+
+use std::path::Path;
+use std::sync::OnceLock;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::TargetType;
+
+/// Kind of a library the target links against.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LibraryKind {
+    /// A static library (an archive), e.g. `libfoo.a` or a static `foo.lib`.
+    Static,
+    /// A shared library, e.g. `libfoo.so.5`, or an import library of a DLL.
+    Dynamic,
+    /// A raw linker item (e.g. `-lpthread`) or a library whose kind could not be determined.
+    #[default]
+    Unknown,
+}
+
+impl LibraryKind {
+    /// Detects the kind of the library from its file name.
+    pub(crate) fn from_path(item: &str) -> Self {
+        let Some(file_name) = Path::new(item).file_name().and_then(|name| name.to_str()) else {
+            return LibraryKind::Unknown;
+        };
+
+        // MinGW import libraries (libfoo.dll.a) are archives, but they link against a DLL
+        if file_name.ends_with(".dll.a") {
+            LibraryKind::Dynamic
+        } else if file_name.ends_with(".a") {
+            LibraryKind::Static
+        } else if shared_library_suffix().is_match(file_name) {
+            LibraryKind::Dynamic
+        } else {
+            LibraryKind::Unknown
+        }
+    }
+
+    /// Detects the kind of the library from the [`TYPE`][cmake_type] of the imported target
+    /// that provides it, falling back to the file name for `UNKNOWN_LIBRARY` targets.
+    ///
+    /// [cmake_type]: https://cmake.org/cmake/help/latest/prop_tgt/TYPE.html
+    pub(crate) fn from_target_type(target_type: TargetType, item: &str) -> Self {
+        match target_type {
+            TargetType::StaticLibrary => LibraryKind::Static,
+            TargetType::SharedLibrary | TargetType::ModuleLibrary => LibraryKind::Dynamic,
+            _ => Self::from_path(item),
+        }
+    }
+}
+
+/// A single library from the [`link_libraries`][crate::CMakeTarget::link_libraries] of a target.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LinkLibrary {
+    /// Path to the library file, or a raw item from the `INTERFACE_LINK_LIBRARIES` property
+    /// (e.g. `-lpthread`).
+    pub item: String,
+    /// Name of the imported CMake target that provides the library, if any.
+    pub target: Option<String>,
+    /// Type of the imported CMake target that provides the library, if any.
+    pub target_type: Option<TargetType>,
+    /// Kind of the library.
+    pub kind: LibraryKind,
+    /// Feature to link the library with, given by the [`$<LINK_LIBRARY:...>`][cmake_link_library]
+    /// expression, e.g. `WHOLE_ARCHIVE`.
+    ///
+    /// [cmake_link_library]: https://cmake.org/cmake/help/latest/manual/cmake-generator-expressions.7.html#genex:LINK_LIBRARY
+    pub feature: Option<String>,
+    /// Feature of the group the library belongs to, given by the [`$<LINK_GROUP:...>`][cmake_link_group]
+    /// expression, e.g. `RESCAN`. Consecutive libraries with the same group feature form a single group.
+    ///
+    /// [cmake_link_group]: https://cmake.org/cmake/help/latest/manual/cmake-generator-expressions.7.html#genex:LINK_GROUP
+    pub group: Option<String>,
+}
+
+impl LinkLibrary {
+    /// Creates a library for a raw link item, the kind of the library is detected from the file name.
+    pub(crate) fn new(item: impl Into<String>) -> Self {
+        let item = item.into();
+        Self {
+            kind: LibraryKind::from_path(&item),
+            item,
+            target: None,
+            target_type: None,
+            feature: None,
+            group: None,
+        }
+    }
+}
+
+/// Preferred kind of libraries to link against when a package provides both static and shared
+/// variants of a library.
+///
+/// See [`FindPackageBuilder::link_preference()`][crate::FindPackageBuilder::link_preference].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LinkPreference {
+    /// Link against the libraries chosen by the CMake package.
+    #[default]
+    Default,
+    /// Prefer static libraries.
+    Static,
+    /// Prefer shared libraries.
+    Dynamic,
+}
+
+impl LinkPreference {
+    /// Replaces the library with its preferred variant, if the variant exists next to it.
+    pub(crate) fn apply(self, library: LinkLibrary) -> LinkLibrary {
+        let (kind, candidates) = match (self, library.kind) {
+            (LinkPreference::Static, LibraryKind::Dynamic) => (
+                LibraryKind::Static,
+                shared_library_base(&library.item)
+                    .map(|base| vec![format!("{base}.a")])
+                    .unwrap_or_default(),
+            ),
+            (LinkPreference::Dynamic, LibraryKind::Static) => (
+                LibraryKind::Dynamic,
+                library
+                    .item
+                    .strip_suffix(".a")
+                    .map(|base| {
+                        ["so", "dylib", "dll.a"]
+                            .iter()
+                            .map(|suffix| format!("{base}.{suffix}"))
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+            ),
+            _ => return library,
+        };
+
+        match candidates
+            .into_iter()
+            .find(|candidate| Path::new(candidate).is_file())
+        {
+            Some(item) => LinkLibrary {
+                item,
+                kind,
+                ..library
+            },
+            None => library,
+        }
+    }
+}
+
+fn shared_library_suffix() -> &'static Regex {
+    static SUFFIX: OnceLock<Regex> = OnceLock::new();
+    SUFFIX.get_or_init(|| {
+        Regex::new(r"(\.so(\.[0-9]+)*|(\.[0-9]+)*\.dylib|\.tbd|\.dll|\.dll\.a)$").unwrap()
+    })
+}
+
+/// Strips the shared library suffix (including the version) from the path to a shared library,
+/// e.g. `/usr/lib/libfoo.so.5` becomes `/usr/lib/libfoo`.
+fn shared_library_base(item: &str) -> Option<&str> {
+    let suffix = shared_library_suffix().find(item)?;
+    Some(&item[..suffix.start()])
+}
+
+/// The platform the crate is built for.
+///
+/// Build scripts are compiled for the host, so `cfg!(target_os = ...)` describes the host rather than
+/// the target when cross-compiling. The target is described by the `CARGO_CFG_TARGET_OS`,
+/// `CARGO_CFG_TARGET_ENV` and `CARGO_CFG_TARGET_VENDOR` environment variables set by Cargo instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TargetPlatform {
+    os: String,
+    env: String,
+    vendor: String,
+}
+
+/// How rustc should link a library, see [`TargetPlatform::link_name()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LinkKind {
+    Static,
+    Dylib,
+    /// A shared library that `-l<name>` would not find, linked by its exact file name with the
+    /// [`verbatim`][rustc_verbatim] modifier.
+    ///
+    /// [rustc_verbatim]: https://doc.rust-lang.org/rustc/command-line-arguments.html#linking-modifiers-verbatim
+    Verbatim,
+    Framework,
+}
+
+/// The name of a library for `cargo:rustc-link-lib` and the directory to search for it in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct LinkName<'a> {
+    pub(crate) kind: LinkKind,
+    pub(crate) dir: &'a Path,
+    pub(crate) name: &'a str,
+}
+
+/// A library from the [`link_libraries`][crate::CMakeTarget::link_libraries] of a target mapped to
+/// the Cargo directive that links it, see [`TargetPlatform::classify()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LinkItem<'a> {
+    /// A library file, linked by its name and searched for in its directory.
+    File(LinkName<'a>),
+    /// A library given only by its name, e.g. `-lpthread` or `m`, searched for in the search paths.
+    Name(LinkKind, &'a str),
+    /// A directory to search for the libraries (`-L<dir>`) or the frameworks (`-F<dir>`) in.
+    SearchPath { framework: bool, dir: &'a str },
+    /// A raw linker argument, e.g. `-Wl,--as-needed`.
+    Arg(&'a str),
+    /// A name of a CMake target that is not defined, e.g. `Threads::Threads` when the package
+    /// doesn't look up its own dependencies.
+    Target(&'a str),
+}
+
+impl TargetPlatform {
+    pub(crate) fn new(os: &str, env: &str, vendor: &str) -> Self {
+        Self {
+            os: os.to_string(),
+            env: env.to_string(),
+            vendor: vendor.to_string(),
+        }
+    }
+
+    /// Returns the target platform as given by Cargo, falling back to the host when the variables
+    /// are not set (i.e. outside of a build script).
+    pub(crate) fn from_env() -> Self {
+        let getenv =
+            |var: &str, host: &str| std::env::var(var).unwrap_or_else(|_| host.to_string());
+        let host_env = if cfg!(target_env = "msvc") {
+            "msvc"
+        } else if cfg!(target_env = "gnu") {
+            "gnu"
+        } else {
+            ""
+        };
+        let host_vendor = if cfg!(target_vendor = "apple") {
+            "apple"
+        } else if cfg!(target_os = "windows") {
+            "pc"
+        } else {
+            "unknown"
+        };
+        Self::new(
+            &getenv("CARGO_CFG_TARGET_OS", std::env::consts::OS),
+            &getenv("CARGO_CFG_TARGET_ENV", host_env),
+            &getenv("CARGO_CFG_TARGET_VENDOR", host_vendor),
+        )
+    }
+
+    /// Whether the linker needs `--start-group` and `--end-group` to resolve circular dependencies
+    /// between static libraries. The Apple and MSVC linkers search the libraries repeatedly anyway.
+    pub(crate) fn needs_link_groups(&self) -> bool {
+        !self.is_apple() && !self.is_msvc()
+    }
+
+    /// Whether the target is Windows, where the import libraries rather than the DLLs are linked.
+    pub(crate) fn is_windows(&self) -> bool {
+        self.os == "windows"
+    }
+
+    fn is_msvc(&self) -> bool {
+        self.is_windows() && self.env == "msvc"
+    }
+
+    fn is_apple(&self) -> bool {
+        self.vendor == "apple"
+    }
+
+    /// Turns the path to a library into the name to pass to rustc, e.g. `/usr/lib/libfoo.so.5`
+    /// into `foo`, so that `-lfoo` rather than `-l/usr/lib/libfoo.so.5` is passed to the linker.
+    ///
+    /// The recognized file names depend on the target:
+    /// * ELF platforms: `libfoo.so`, `libfoo.so.5` and `libfoo.a`
+    /// * Apple platforms: `libfoo.dylib`, `libfoo.5.dylib`, `libfoo.tbd`, `libfoo.a` and
+    ///   `Foo.framework` (or the binary inside of it)
+    /// * Windows MSVC: `foo.lib`, which is either a static library or an import library, depending
+    ///   on the `kind`
+    /// * Windows GNU: `libfoo.dll.a` and `foo.dll.a` import libraries, `libfoo.a` and `foo.lib`
+    ///
+    /// Shared libraries that `-l<name>` would not resolve to, e.g. `libfoo.so.5` without the
+    /// `libfoo.so` symlink next to it, or `foo.so` without the `lib` prefix, are linked by their exact
+    /// file name as [`LinkKind::Verbatim`]. Apple linkers don't support that, so `None` is returned
+    /// for them instead, and the full path is passed to the linker.
+    ///
+    /// Returns `None` if the path is not a library file of the target (e.g. a raw linker flag).
+    pub(crate) fn link_name<'a>(&self, lib: &'a str, kind: LibraryKind) -> Option<LinkName<'a>> {
+        let path = Path::new(lib);
+        if self.is_apple() {
+            if let Some(framework) = path
+                .ancestors()
+                .find(|path| path.extension().is_some_and(|ext| ext == "framework"))
+            {
+                return Some(LinkName {
+                    kind: LinkKind::Framework,
+                    dir: framework.parent()?,
+                    name: framework.file_stem()?.to_str()?,
+                });
+            }
+        }
+
+        let dir = path.parent()?;
+        let file_name = path.file_name()?.to_str()?;
+        let (kind, name) = if self.is_msvc() {
+            let name = file_name.strip_suffix(".lib")?;
+            match kind {
+                LibraryKind::Static => (LinkKind::Static, name),
+                _ => (LinkKind::Dylib, name),
+            }
+        } else if self.is_windows() {
+            if let Some(name) = file_name.strip_suffix(".dll.a") {
+                (LinkKind::Dylib, name.strip_prefix("lib").unwrap_or(name))
+            } else if let Some(name) = file_name.strip_suffix(".lib") {
+                (LinkKind::Dylib, name)
+            } else {
+                (LinkKind::Static, strip_lib(file_name, ".a")?)
+            }
+        } else if let Some(name) = strip_lib(file_name, ".a") {
+            (LinkKind::Static, name)
+        } else if self.is_apple() {
+            let name = strip_lib(file_name, ".tbd").or_else(|| {
+                let name = strip_lib(file_name, ".dylib")?;
+                // libfoo.5.dylib
+                Some(name.split_once('.').map_or(name, |(name, _)| name))
+            })?;
+            let resolves = ["dylib", "tbd"].iter().any(|ext| {
+                let linked = format!("lib{name}.{ext}");
+                file_name == linked || same_file(path, &dir.join(linked))
+            });
+            if !resolves {
+                return None;
+            }
+            (LinkKind::Dylib, name)
+        } else {
+            // libfoo.so.5 or foo.so
+            let (stem, version) = file_name.split_once(".so")?;
+            if !(version.is_empty() || version.starts_with('.')) {
+                return None;
+            }
+            match stem.strip_prefix("lib") {
+                Some(name)
+                    if version.is_empty()
+                        || same_file(path, &dir.join(format!("lib{name}.so"))) =>
+                {
+                    (LinkKind::Dylib, name)
+                }
+                _ => (LinkKind::Verbatim, file_name),
+            }
+        };
+        (!name.is_empty()).then_some(LinkName { kind, dir, name })
+    }
+}
+
+impl TargetPlatform {
+    /// Maps the libraries to the Cargo directives that link them.
+    ///
+    /// Besides the paths to the library files, packages put raw linker items into their
+    /// `INTERFACE_LINK_LIBRARIES`, which CMake passes to the linker mostly as they are: `-l<name>`,
+    /// `-L<dir>`, `-F<dir>`, `-framework <name>` (as a single item or as two), other linker flags
+    /// like `-Wl,--as-needed` or `-pthread`, and bare library names like `m` or `ws2_32`.
+    ///
+    /// Each item is returned together with the library it comes from.
+    pub(crate) fn classify<'a>(
+        &self,
+        libraries: &'a [LinkLibrary],
+    ) -> Vec<(&'a LinkLibrary, LinkItem<'a>)> {
+        let mut items = Vec::new();
+        let mut libraries = libraries.iter();
+        while let Some(library) = libraries.next() {
+            let item = library.item.trim();
+            if item == "-framework" {
+                // A trailing `-framework` without the name is left for the linker to report
+                let item = match libraries.next() {
+                    Some(framework) => LinkItem::Name(LinkKind::Framework, framework.item.trim()),
+                    None => LinkItem::Arg(item),
+                };
+                items.push((library, item));
+                continue;
+            }
+            items.push((library, self.classify_item(item, library.kind)));
+        }
+        items
+    }
+
+    fn classify_item<'a>(&self, item: &'a str, kind: LibraryKind) -> LinkItem<'a> {
+        if let Some(framework) = item
+            .strip_prefix("-framework")
+            .filter(|name| name.starts_with(char::is_whitespace))
+        {
+            LinkItem::Name(LinkKind::Framework, framework.trim())
+        } else if let Some(file_name) = item.strip_prefix("-l:") {
+            LinkItem::Name(LinkKind::Verbatim, file_name)
+        } else if let Some(name) = item.strip_prefix("-l").filter(|name| !name.is_empty()) {
+            LinkItem::Name(LinkKind::Dylib, name)
+        } else if let Some(dir) = item.strip_prefix("-L").filter(|dir| !dir.is_empty()) {
+            LinkItem::SearchPath {
+                framework: false,
+                dir,
+            }
+        } else if let Some(dir) = item.strip_prefix("-F").filter(|dir| !dir.is_empty()) {
+            LinkItem::SearchPath {
+                framework: true,
+                dir,
+            }
+        } else if item.starts_with('-') {
+            LinkItem::Arg(item)
+        } else if item.contains("::") {
+            LinkItem::Target(item)
+        } else if item.contains(['/', '\\']) {
+            match self.link_name(item, kind) {
+                Some(link_name) => LinkItem::File(link_name),
+                // Let the linker deal with whatever the file is
+                None => LinkItem::Arg(item),
+            }
+        } else {
+            match self.link_name(item, kind) {
+                Some(link_name) => LinkItem::Name(link_name.kind, link_name.name),
+                None => LinkItem::Name(LinkKind::Dylib, item),
+            }
+        }
+    }
+}
+
+/// Whether both paths exist and point to the same file, e.g. `libfoo.so` is a symlink to `libfoo.so.5`.
+fn same_file(path: &Path, other: &Path) -> bool {
+    match (path.canonicalize(), other.canonicalize()) {
+        (Ok(path), Ok(other)) => path == other,
+        _ => false,
+    }
+}
+
+fn strip_lib<'a>(file_name: &'a str, suffix: &str) -> Option<&'a str> {
+    file_name.strip_prefix("lib")?.strip_suffix(suffix)
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+
+    #[test]
+    fn kind_from_path() {
+        assert_eq!(
+            LibraryKind::from_path("/usr/lib/libfoo.a"),
+            LibraryKind::Static
+        );
+        assert_eq!(
+            LibraryKind::from_path("/usr/lib/libfoo.so"),
+            LibraryKind::Dynamic
+        );
+        assert_eq!(
+            LibraryKind::from_path("/usr/lib/libfoo.so.5.1"),
+            LibraryKind::Dynamic
+        );
+        assert_eq!(
+            LibraryKind::from_path("/opt/lib/libfoo.1.dylib"),
+            LibraryKind::Dynamic
+        );
+        assert_eq!(
+            LibraryKind::from_path("C:/foo/libfoo.dll.a"),
+            LibraryKind::Dynamic
+        );
+        assert_eq!(
+            LibraryKind::from_path("C:/foo/foo.lib"),
+            LibraryKind::Unknown
+        );
+        assert_eq!(LibraryKind::from_path("-lpthread"), LibraryKind::Unknown);
+    }
+
+    #[test]
+    fn kind_from_target_type() {
+        assert_eq!(
+            LibraryKind::from_target_type(TargetType::StaticLibrary, "C:/foo/foo.lib"),
+            LibraryKind::Static
+        );
+        assert_eq!(
+            LibraryKind::from_target_type(TargetType::SharedLibrary, "C:/foo/foo.lib"),
+            LibraryKind::Dynamic
+        );
+        assert_eq!(
+            LibraryKind::from_target_type(TargetType::UnknownLibrary, "/usr/lib/libfoo.a"),
+            LibraryKind::Static
+        );
+        assert_eq!(
+            LibraryKind::from_target_type(TargetType::UnknownLibrary, "/usr/lib/libfoo.so"),
+            LibraryKind::Dynamic
+        );
+    }
+
+    fn classify(platform: &TargetPlatform, items: &[&str]) -> Vec<String> {
+        let libraries = items
+            .iter()
+            .map(|item| LinkLibrary::new(*item))
+            .collect::<Vec<_>>();
+        platform
+            .classify(&libraries)
+            .into_iter()
+            .map(|(_, item)| match item {
+                LinkItem::File(link_name) => format!(
+                    "file {:?} {} in {}",
+                    link_name.kind,
+                    link_name.name,
+                    link_name.dir.display()
+                ),
+                LinkItem::Name(kind, name) => format!("name {kind:?} {name}"),
+                LinkItem::SearchPath { framework, dir } => {
+                    format!(
+                        "search {} {dir}",
+                        if framework { "framework" } else { "native" }
+                    )
+                }
+                LinkItem::Arg(arg) => format!("arg {arg}"),
+                LinkItem::Target(target) => format!("target {target}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn classify_openssl() {
+        let linux = TargetPlatform::new("linux", "gnu", "unknown");
+        // OpenSSL::Crypto from FindOpenSSL with OPENSSL_USE_STATIC_LIBS
+        assert_eq!(
+            classify(
+                &linux,
+                &[
+                    "/usr/lib/x86_64-linux-gnu/libcrypto.a",
+                    "dl",
+                    "Threads::Threads"
+                ]
+            ),
+            [
+                "file Static crypto in /usr/lib/x86_64-linux-gnu",
+                "name Dylib dl",
+                "target Threads::Threads",
+            ]
+        );
+
+        let msvc = TargetPlatform::new("windows", "msvc", "pc");
+        assert_eq!(
+            classify(
+                &msvc,
+                &[
+                    "C:/Program Files/OpenSSL-Win64/lib/VC/x64/MD/libcrypto.lib",
+                    "ws2_32",
+                    "crypt32.lib"
+                ]
+            ),
+            [
+                "file Dylib libcrypto in C:/Program Files/OpenSSL-Win64/lib/VC/x64/MD",
+                "name Dylib ws2_32",
+                "name Dylib crypt32",
+            ]
+        );
+    }
+
+    #[test]
+    fn classify_qt() {
+        let macos = TargetPlatform::new("macos", "", "apple");
+        // Qt5::Core from a framework build, the frameworks are listed both ways
+        assert_eq!(
+            classify(
+                &macos,
+                &[
+                    "/opt/Qt/5.15.2/clang_64/lib/QtCore.framework/QtCore",
+                    "-framework",
+                    "DiskArbitration",
+                    "-framework IOKit",
+                    "-F/opt/Qt/5.15.2/clang_64/lib",
+                    "-lz",
+                ]
+            ),
+            [
+                "file Framework QtCore in /opt/Qt/5.15.2/clang_64/lib",
+                "name Framework DiskArbitration",
+                "name Framework IOKit",
+                "search framework /opt/Qt/5.15.2/clang_64/lib",
+                "name Dylib z",
+            ]
+        );
+        assert_eq!(
+            classify(&macos, &["-framework", "Cocoa", "-framework"]),
+            ["name Framework Cocoa", "arg -framework"]
+        );
+
+        let linux = TargetPlatform::new("linux", "gnu", "unknown");
+        // Static Qt6::Core
+        assert_eq!(
+            classify(
+                &linux,
+                &[
+                    "/opt/qt6-static/lib/libQt6Core.a",
+                    "-lpthread",
+                    "-Wl,--export-dynamic",
+                    "m",
+                    "-l:libicuuc.so.70",
+                ]
+            ),
+            [
+                "file Static Qt6Core in /opt/qt6-static/lib",
+                "name Dylib pthread",
+                "arg -Wl,--export-dynamic",
+                "name Dylib m",
+                "name Verbatim libicuuc.so.70",
+            ]
+        );
+    }
+
+    #[test]
+    fn classify_boost() {
+        let linux = TargetPlatform::new("linux", "gnu", "unknown");
+        // Boost::thread from BoostConfig, without the dev symlink
+        assert_eq!(
+            classify(
+                &linux,
+                &["/opt/boost/lib/libboost_thread.so.1.83.0", "-pthread", "rt"]
+            ),
+            [
+                "file Verbatim libboost_thread.so.1.83.0 in /opt/boost/lib",
+                "arg -pthread",
+                "name Dylib rt",
+            ]
+        );
+
+        let mingw = TargetPlatform::new("windows", "gnu", "pc");
+        assert_eq!(
+            classify(
+                &mingw,
+                &[
+                    "C:/msys64/mingw64/lib/libboost_thread-mt.dll.a",
+                    "-lws2_32",
+                    "bcrypt"
+                ]
+            ),
+            [
+                "file Dylib boost_thread-mt in C:/msys64/mingw64/lib",
+                "name Dylib ws2_32",
+                "name Dylib bcrypt",
+            ]
+        );
+    }
+
+    #[test]
+    fn classify_zlib() {
+        let linux = TargetPlatform::new("linux", "gnu", "unknown");
+        assert_eq!(
+            classify(&linux, &["/usr/lib/x86_64-linux-gnu/libz.so"]),
+            ["file Dylib z in /usr/lib/x86_64-linux-gnu"]
+        );
+        // zlib as imported from pkg-config
+        assert_eq!(
+            classify(&linux, &["-L/opt/zlib/lib", "-lz", "-Wl,--as-needed"]),
+            [
+                "search native /opt/zlib/lib",
+                "name Dylib z",
+                "arg -Wl,--as-needed"
+            ]
+        );
+
+        let macos = TargetPlatform::new("macos", "", "apple");
+        assert_eq!(
+            classify(
+                &macos,
+                &["/Applications/Xcode.app/Contents/Developer/Platforms/MacOSX.platform/Developer/SDKs/MacOSX.sdk/usr/lib/libz.tbd"]
+            ),
+            ["file Dylib z in /Applications/Xcode.app/Contents/Developer/Platforms/MacOSX.platform/Developer/SDKs/MacOSX.sdk/usr/lib"]
+        );
+    }
+
+    #[test]
+    fn link_names() {
+        let name = |platform: &TargetPlatform, lib, kind| {
+            platform
+                .link_name(lib, kind)
+                .map(|name| (name.kind, name.dir.to_str().unwrap(), name.name))
+        };
+
+        let linux = TargetPlatform::new("linux", "gnu", "unknown");
+        assert_eq!(
+            name(&linux, "/usr/lib/libfoo.so", LibraryKind::Dynamic),
+            Some((LinkKind::Dylib, "/usr/lib", "foo"))
+        );
+        assert_eq!(
+            name(&linux, "/usr/lib64/libfoo-2.0.so", LibraryKind::Dynamic),
+            Some((LinkKind::Dylib, "/usr/lib64", "foo-2.0"))
+        );
+        assert_eq!(
+            name(&linux, "/usr/lib/libfoo.a", LibraryKind::Static),
+            Some((LinkKind::Static, "/usr/lib", "foo"))
+        );
+        assert_eq!(
+            name(&linux, "/usr/lib/libfoo.sox", LibraryKind::Unknown),
+            None
+        );
+        assert_eq!(name(&linux, "/usr/lib/foo.a", LibraryKind::Static), None);
+        assert_eq!(
+            name(&linux, "/usr/lib/libfoo.dylib", LibraryKind::Dynamic),
+            None
+        );
+        assert_eq!(name(&linux, "-lpthread", LibraryKind::Unknown), None);
+
+        let macos = TargetPlatform::new("macos", "", "apple");
+        assert_eq!(
+            name(&macos, "/opt/lib/libfoo.dylib", LibraryKind::Dynamic),
+            Some((LinkKind::Dylib, "/opt/lib", "foo"))
+        );
+        assert_eq!(
+            name(&macos, "/usr/lib/libz.tbd", LibraryKind::Dynamic),
+            Some((LinkKind::Dylib, "/usr/lib", "z"))
+        );
+        assert_eq!(
+            name(&macos, "/opt/lib/libfoo.a", LibraryKind::Static),
+            Some((LinkKind::Static, "/opt/lib", "foo"))
+        );
+        assert_eq!(
+            name(
+                &macos,
+                "/Library/Frameworks/Foo.framework",
+                LibraryKind::Unknown
+            ),
+            Some((LinkKind::Framework, "/Library/Frameworks", "Foo"))
+        );
+        assert_eq!(
+            name(
+                &macos,
+                "/opt/Qt/lib/QtCore.framework/Versions/A/QtCore",
+                LibraryKind::Dynamic
+            ),
+            Some((LinkKind::Framework, "/opt/Qt/lib", "QtCore"))
+        );
+        assert_eq!(
+            name(&macos, "/usr/lib/libfoo.so", LibraryKind::Dynamic),
+            None
+        );
+
+        let msvc = TargetPlatform::new("windows", "msvc", "pc");
+        assert_eq!(
+            name(&msvc, "C:/foo/lib/foo.lib", LibraryKind::Dynamic),
+            Some((LinkKind::Dylib, "C:/foo/lib", "foo"))
+        );
+        assert_eq!(
+            name(&msvc, "C:/foo/lib/foo_static.lib", LibraryKind::Static),
+            Some((LinkKind::Static, "C:/foo/lib", "foo_static"))
+        );
+        assert_eq!(
+            name(&msvc, "C:/foo/lib/libfoo.a", LibraryKind::Static),
+            None
+        );
+
+        let mingw = TargetPlatform::new("windows", "gnu", "pc");
+        assert_eq!(
+            name(
+                &mingw,
+                "C:/msys64/mingw64/lib/libfoo.dll.a",
+                LibraryKind::Dynamic
+            ),
+            Some((LinkKind::Dylib, "C:/msys64/mingw64/lib", "foo"))
+        );
+        assert_eq!(
+            name(&mingw, "C:/foo/lib/foo.dll.a", LibraryKind::Dynamic),
+            Some((LinkKind::Dylib, "C:/foo/lib", "foo"))
+        );
+        assert_eq!(
+            name(
+                &mingw,
+                "C:/msys64/mingw64/lib/libfoo.a",
+                LibraryKind::Static
+            ),
+            Some((LinkKind::Static, "C:/msys64/mingw64/lib", "foo"))
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn verbatim_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = |name: &str| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, "").unwrap();
+            path.to_str().unwrap().to_string()
+        };
+        let symlink = |name: &str, target: &str| {
+            std::os::unix::fs::symlink(target, dir.path().join(name)).unwrap();
+        };
+        let name = |platform: &TargetPlatform, lib| {
+            platform
+                .link_name(lib, LibraryKind::Dynamic)
+                .map(|name| (name.kind, name.name))
+        };
+        let linux = TargetPlatform::new("linux", "gnu", "unknown");
+        let macos = TargetPlatform::new("macos", "", "apple");
+
+        // The dev symlink resolves to the library
+        let foo = file("libfoo.so.5");
+        symlink("libfoo.so", "libfoo.so.5");
+        assert_eq!(name(&linux, &foo), Some((LinkKind::Dylib, "foo")));
+
+        // No dev symlink
+        let bar = file("libbar.so.1.2");
+        assert_eq!(
+            name(&linux, &bar),
+            Some((LinkKind::Verbatim, "libbar.so.1.2"))
+        );
+
+        // The dev symlink points to a different version
+        let baz = file("libbaz.so.1");
+        file("libbaz.so.2");
+        symlink("libbaz.so", "libbaz.so.2");
+        assert_eq!(
+            name(&linux, &baz),
+            Some((LinkKind::Verbatim, "libbaz.so.1"))
+        );
+
+        // No lib prefix
+        let plugin = file("plugin.so");
+        assert_eq!(
+            name(&linux, &plugin),
+            Some((LinkKind::Verbatim, "plugin.so"))
+        );
+
+        // Apple linkers get the full path instead
+        let qux = file("libqux.1.dylib");
+        assert_eq!(name(&macos, &qux), None);
+        symlink("libqux.dylib", "libqux.1.dylib");
+        assert_eq!(name(&macos, &qux), Some((LinkKind::Dylib, "qux")));
+    }
+
+    #[test]
+    fn preference() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = |name: &str| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, "").unwrap();
+            path.to_str().unwrap().to_string()
+        };
+        let shared = file("libfoo.so.5");
+        let archive = file("libfoo.a");
+        let dev_symlink = file("libfoo.so");
+        let shared_only = file("libbar.so");
+
+        let library = LinkPreference::Static.apply(LinkLibrary::new(&shared));
+        assert_eq!(library.item, archive);
+        assert_eq!(library.kind, LibraryKind::Static);
+
+        // No static variant available
+        let library = LinkPreference::Static.apply(LinkLibrary::new(&shared_only));
+        assert_eq!(library.item, shared_only);
+        assert_eq!(library.kind, LibraryKind::Dynamic);
+
+        let library = LinkPreference::Dynamic.apply(LinkLibrary::new(&archive));
+        assert_eq!(library.item, dev_symlink);
+        assert_eq!(library.kind, LibraryKind::Dynamic);
+
+        let library = LinkPreference::Default.apply(LinkLibrary::new(&shared));
+        assert_eq!(library.item, shared);
+    }
+}
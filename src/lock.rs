@@ -0,0 +1,322 @@
+// SPDX-FileCopyrightText: 2024 Daniel Vrátil <dvratil@kde.org>
+//
+// SPDX-License-Identifier: MIT
+
+//! Recording of the resolved packages into a lock file and replaying them from it.
+//!
+//! With `CMAKE_PACKAGE_RECORD=<path>`, every package found and every target or executable
+//! resolved is written into the JSON lock file at `<path>`. With `CMAKE_PACKAGE_REPLAY=<path>`,
+//! the packages, targets and executables are read from the lock file instead, without running
+//! CMake at all. This allows building in environments without CMake, as long as the libraries
+//! are in the same locations as where the lock file was recorded.
+//!
+//! Packages are recorded separately for each set of options they were found with (build type,
+//! prefix paths, cache variables, languages and toolchain file), and only replayed with the same
+//! options.
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::cmake::{self, CMakeOptions, Error};
+use crate::toolchain;
+use crate::version::{Version, VersionError};
+use crate::{CMakeExecutable, CMakePackage, CMakeTarget, Source};
+
+/// Environment variable with the path to the lock file to record the packages into.
+pub(crate) const RECORD_ENV: &str = "CMAKE_PACKAGE_RECORD";
+/// Environment variable with the path to the lock file to replay the packages from.
+pub(crate) const REPLAY_ENV: &str = "CMAKE_PACKAGE_REPLAY";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct LockFile {
+    #[serde(default)]
+    packages: Vec<LockedPackage>,
+}
+
+/// A package recorded in the lock file, together with all its targets that were resolved.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct LockedPackage {
+    name: String,
+    version: Option<String>,
+    components: Option<Vec<String>>,
+    /// The options the package was found with, see [`CMakeOptions::describe()`].
+    options: Vec<String>,
+    available_targets: Vec<String>,
+    config_paths: Vec<PathBuf>,
+    pub(crate) targets: BTreeMap<String, CMakeTarget>,
+    pub(crate) executables: BTreeMap<String, CMakeExecutable>,
+}
+
+impl LockFile {
+    fn read(path: &Path) -> Result<Self, Error> {
+        cmake::read_output(path)
+    }
+
+    /// Writes the lock file atomically, so that it is never read half-written.
+    fn write(&self, path: &Path) -> Result<(), Error> {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let mut file = tempfile::NamedTempFile::new_in(dir).map_err(Error::IO)?;
+        serde_json::to_writer_pretty(&mut file, self)
+            .map_err(std::io::Error::from)
+            .and_then(|_| writeln!(file))
+            .map_err(Error::IO)?;
+        file.persist(path).map_err(|err| Error::IO(err.error))?;
+        Ok(())
+    }
+
+    fn find(
+        &self,
+        name: &str,
+        components: &Option<Vec<String>>,
+        options: &[String],
+    ) -> Option<&LockedPackage> {
+        self.packages
+            .iter()
+            .find(|package| package.is(name, components, options))
+    }
+}
+
+impl LockedPackage {
+    /// Whether this is the package `name` with the `components`, found with the `options`.
+    fn is(&self, name: &str, components: &Option<Vec<String>>, options: &[String]) -> bool {
+        self.name == name && self.components == *components && self.options == options
+    }
+}
+
+/// Takes an exclusive advisory lock for updating the lock file at `path`, released when the
+/// returned file is dropped.
+///
+/// Build scripts of several crates may record into the same lock file in parallel. The lock
+/// is taken on a separate file, since the lock file itself is replaced when written.
+fn lock(path: &Path) -> Result<File, Error> {
+    let mut guard = OsString::from(path);
+    guard.push(".guard");
+    let file = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(guard)
+        .map_err(Error::IO)?;
+    file.lock().map_err(Error::IO)?;
+    Ok(file)
+}
+
+fn env_path(var: &str) -> Option<PathBuf> {
+    std::env::var_os(var)
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Returns the path to the lock file to replay the packages from, if replaying is enabled.
+pub(crate) fn replay_path() -> Option<PathBuf> {
+    env_path(REPLAY_ENV)
+}
+
+/// Finds the package in the lock file at `path`.
+///
+/// Returns [`Error::PackageNotFound`] if the package with the same components and options was not
+/// recorded and [`Error::Version`] if the recorded version is too old.
+pub(crate) fn replay_package(
+    path: &Path,
+    name: String,
+    version: Option<Version>,
+    components: Option<Vec<String>>,
+    mut options: CMakeOptions,
+) -> Result<CMakePackage, Error> {
+    println!("cargo:rerun-if-changed={}", path.display());
+    println!("cargo:rerun-if-env-changed={REPLAY_ENV}");
+
+    // Resolve the toolchain file the same way as when the package was found
+    options.toolchain_file = toolchain::requested(options.toolchain_file.take())?;
+
+    let lock_file = LockFile::read(path)?;
+    let locked = lock_file
+        .find(&name, &components, &options.describe(&name))
+        .ok_or(Error::PackageNotFound)?;
+
+    let package_version = match &locked.version {
+        Some(version) => Some(Version::parse(version).map_err(Error::Version)?),
+        None => None,
+    };
+    if let (Some(version), Some(package_version)) = (version, package_version) {
+        if package_version < version {
+            return Err(Error::Version(VersionError::VersionTooOld(package_version)));
+        }
+    }
+
+    Ok(CMakePackage::new(
+        Source::Replay(locked.clone()),
+        locked.name.clone(),
+        package_version,
+        locked.components.clone(),
+        locked.available_targets.clone(),
+        locked.config_paths.clone(),
+        options,
+    ))
+}
+
+/// Records the `package` into the lock file given by `CMAKE_PACKAGE_RECORD`, if recording is enabled.
+///
+/// The recorded entry of the package is updated by `update`, e.g. to add the resolved targets.
+pub(crate) fn record(
+    package: &CMakePackage,
+    update: impl FnOnce(&mut LockedPackage),
+) -> Result<(), Error> {
+    let Some(path) = env_path(RECORD_ENV) else {
+        return Ok(());
+    };
+
+    let _guard = lock(&path)?;
+    let mut lock_file = if path.exists() {
+        LockFile::read(&path)?
+    } else {
+        LockFile::default()
+    };
+
+    let options = package.options.describe(&package.name);
+    if lock_file
+        .find(&package.name, &package.components, &options)
+        .is_none()
+    {
+        lock_file.packages.push(LockedPackage {
+            name: package.name.clone(),
+            components: package.components.clone(),
+            options: options.clone(),
+            ..Default::default()
+        });
+    }
+    let locked = lock_file
+        .packages
+        .iter_mut()
+        .find(|locked| locked.is(&package.name, &package.components, &options))
+        .expect("package was just added");
+    locked.version = package.version.map(|version| version.to_string());
+    locked.available_targets = package.available_targets().to_vec();
+    locked.config_paths = package.config_paths().to_vec();
+    update(locked);
+
+    lock_file.write(&path)
+}
+
+#[cfg(test)]
+mod testing {
+    use scopeguard::defer;
+    use serial_test::serial;
+
+    use super::*;
+    use crate::cmake::CMakeBuildType;
+    use crate::{LinkLibrary, TargetType};
+
+    #[test]
+    #[serial]
+    fn record_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cmake-package.lock");
+        std::env::set_var(RECORD_ENV, &path);
+        defer! {
+            std::env::remove_var(RECORD_ENV);
+        }
+
+        let package = CMakePackage::new(
+            Source::Replay(LockedPackage::default()),
+            "Foo".into(),
+            Some(Version::parse("1.2").unwrap()),
+            Some(vec!["Core".into()]),
+            vec!["Foo::Core".into()],
+            vec![PathBuf::from("/usr/lib/cmake/Foo/FooConfig.cmake")],
+            CMakeOptions::default(),
+        );
+        let target = CMakeTarget {
+            name: "Foo::Core".into(),
+            target_type: TargetType::SharedLibrary,
+            include_directories: vec!["/usr/include/foo".into()],
+            link_libraries: vec![LinkLibrary::new("/usr/lib/libfoo.so")],
+            ..Default::default()
+        };
+        record(&package, |_| ()).unwrap();
+        record(&package, |locked| {
+            locked.targets.insert(target.name.clone(), target.clone());
+        })
+        .unwrap();
+
+        // The same package found with different options is recorded separately
+        let release = CMakeOptions {
+            build_type: Some(CMakeBuildType::Release),
+            ..Default::default()
+        };
+        record(
+            &CMakePackage::new(
+                Source::Replay(LockedPackage::default()),
+                "Foo".into(),
+                Some(Version::parse("1.3").unwrap()),
+                Some(vec!["Core".into()]),
+                vec!["Foo::Core".into()],
+                vec![PathBuf::from("/opt/foo/lib/cmake/Foo/FooConfig.cmake")],
+                release.clone(),
+            ),
+            |_| (),
+        )
+        .unwrap();
+
+        let replay_with =
+            |version: Option<&str>, components: Option<Vec<String>>, options: CMakeOptions| {
+                replay_package(
+                    &path,
+                    "Foo".into(),
+                    version.map(|version| Version::parse(version).unwrap()),
+                    components,
+                    options,
+                )
+            };
+        let replay = |version: Option<&str>, components: Option<Vec<String>>| {
+            replay_with(version, components, CMakeOptions::default())
+        };
+        let replayed = replay(Some("1.0"), Some(vec!["Core".into()])).unwrap();
+        assert_eq!(replayed.name, "Foo");
+        assert_eq!(replayed.version, Some(Version::parse("1.2").unwrap()));
+        assert_eq!(replayed.available_targets(), ["Foo::Core"]);
+        assert_eq!(replayed.config_paths(), package.config_paths());
+
+        let replayed_target = replayed.target("Foo::Core").unwrap();
+        assert_eq!(replayed_target.target_type, target.target_type);
+        assert_eq!(
+            replayed_target.include_directories,
+            target.include_directories
+        );
+        assert_eq!(replayed_target.link_libraries, target.link_libraries);
+        assert!(matches!(
+            replayed.try_target("Foo::Gui"),
+            Err(Error::TargetNotFound { .. })
+        ));
+
+        assert!(matches!(
+            replay(Some("2.0"), Some(vec!["Core".into()])),
+            Err(Error::Version(VersionError::VersionTooOld(_)))
+        ));
+        assert!(matches!(
+            replay(None, Some(vec!["Gui".into()])),
+            Err(Error::PackageNotFound)
+        ));
+
+        let replayed = replay_with(None, Some(vec!["Core".into()]), release).unwrap();
+        assert_eq!(replayed.version, Some(Version::parse("1.3").unwrap()));
+        assert!(replayed.try_target("Foo::Core").is_err());
+        let prefixed = CMakeOptions {
+            prefix_paths: vec!["/opt/foo".into()],
+            ..Default::default()
+        };
+        assert!(matches!(
+            replay_with(None, Some(vec!["Core".into()]), prefixed),
+            Err(Error::PackageNotFound)
+        ));
+    }
+}
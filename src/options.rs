@@ -0,0 +1,277 @@
+// SPDX-FileCopyrightText: 2024 Daniel Vrátil <dvratil@kde.org>
+//
+// SPDX-License-Identifier: MIT
+
+//! Expansion of the `SHELL:` and `LINKER:` prefixes in compile and link options.
+//!
+//! CMake expands the prefixes only when generating the build system, so the options of imported
+//! targets still contain them after the "configure" step. `SHELL:` groups several options that
+//! must stay together (e.g. `SHELL:-include foo.h`) and are split with the shell quoting rules.
+//! `LINKER:` marks options for the linker (e.g. `LINKER:-z,defs`) that need to be passed through
+//! the compiler driver, see [`CMAKE_<LANG>_LINKER_WRAPPER_FLAG`][cmake_linker_wrapper_flag].
+//!
+//! [cmake_linker_wrapper_flag]: https://cmake.org/cmake/help/latest/variable/CMAKE_LANG_LINKER_WRAPPER_FLAG.html
+
+use crate::genex::Context;
+
+const SHELL_PREFIX: &str = "SHELL:";
+const LINKER_PREFIX: &str = "LINKER:";
+
+/// Splits the `SHELL:` groups in the compile options.
+pub(crate) fn expand_compile_options(options: impl IntoIterator<Item = String>) -> Vec<String> {
+    options
+        .into_iter()
+        .flat_map(|option| match option.strip_prefix(SHELL_PREFIX) {
+            Some(group) => split_shell(group),
+            None => vec![option],
+        })
+        .collect()
+}
+
+/// Splits the `SHELL:` groups in the link options and translates the `LINKER:` options into
+/// options for the compiler driver that is used to link.
+pub(crate) fn expand_link_options(
+    options: impl IntoIterator<Item = String>,
+    context: &Context,
+) -> Vec<String> {
+    let wrapper = LinkerWrapper::new(context);
+    options
+        .into_iter()
+        .flat_map(|option| {
+            if let Some(linker) = option.strip_prefix(LINKER_PREFIX) {
+                let options = match linker.strip_prefix(SHELL_PREFIX) {
+                    Some(group) => split_shell(group),
+                    None => linker.split(',').map(ToString::to_string).collect(),
+                };
+                wrapper.wrap(options)
+            } else if let Some(group) = option.strip_prefix(SHELL_PREFIX) {
+                split_shell(group)
+            } else {
+                vec![option]
+            }
+        })
+        .collect()
+}
+
+/// How the options are passed to the linker through the compiler driver.
+#[derive(Debug, PartialEq)]
+struct LinkerWrapper {
+    /// The flag, e.g. `-Wl,` or `-Xlinker`, possibly consisting of several arguments.
+    flag: Vec<String>,
+    /// Whether the option is a separate argument after the flag (`-Xlinker -z`), rather than
+    /// appended to it (`-Wl,-z`).
+    separate: bool,
+    /// Separator to join all the options into a single argument with, e.g. `,` for `-Wl,-z,defs`.
+    separator: Option<String>,
+}
+
+impl LinkerWrapper {
+    /// Returns the wrapper for the C compiler reported by CMake. When no C compiler is enabled,
+    /// assumes `-Wl,`, except for MSVC, which links with `link.exe` directly and takes each option
+    /// as a separate argument.
+    fn new(context: &Context) -> Self {
+        let Some(flag) = &context.linker_wrapper_flag else {
+            let msvc = context.c_compiler_id == "MSVC"
+                || (context.c_compiler_id.is_empty() && context.platform_id == "Windows");
+            if msvc {
+                return Self {
+                    flag: vec![],
+                    separate: false,
+                    separator: None,
+                };
+            }
+            return Self {
+                flag: vec!["-Wl,".into()],
+                separate: false,
+                separator: Some(",".into()),
+            };
+        };
+
+        // The flag is a CMake list, with a trailing space element when the options are separate
+        let mut flag = flag
+            .split(';')
+            .filter(|part| !part.is_empty())
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        let separate = flag.last().is_some_and(|part| part == " ");
+        if separate {
+            flag.pop();
+        }
+        Self {
+            flag,
+            separate,
+            separator: context
+                .linker_wrapper_flag_sep
+                .clone()
+                .filter(|separator| !separator.is_empty()),
+        }
+    }
+
+    fn wrap(&self, options: Vec<String>) -> Vec<String> {
+        let options = match &self.separator {
+            Some(separator) if !options.is_empty() => vec![options.join(separator)],
+            _ => options,
+        };
+        let Some((last, flag)) = self.flag.split_last() else {
+            return options;
+        };
+        options
+            .into_iter()
+            .flat_map(|option| {
+                let mut args = flag.to_vec();
+                if self.separate {
+                    args.extend([last.clone(), option]);
+                } else {
+                    args.push(format!("{last}{option}"));
+                }
+                args
+            })
+            .collect()
+    }
+}
+
+/// Splits the `value` into arguments with the quoting rules of a Unix shell, the same way as
+/// CMake's `separate_arguments(UNIX_COMMAND)`.
+fn split_shell(value: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut arg: Option<String> = None;
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => args.extend(arg.take()),
+            '\'' => {
+                let arg = arg.get_or_insert_with(String::new);
+                arg.extend(chars.by_ref().take_while(|&c| c != '\''));
+            }
+            '"' => {
+                let arg = arg.get_or_insert_with(String::new);
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => match chars.next() {
+                            Some(escaped @ ('"' | '\\' | '$' | '`')) => arg.push(escaped),
+                            Some(other) => arg.extend(['\\', other]),
+                            None => arg.push('\\'),
+                        },
+                        c => arg.push(c),
+                    }
+                }
+            }
+            '\\' => arg.get_or_insert_with(String::new).extend(chars.next()),
+            c => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+    args.extend(arg);
+    args
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(ToString::to_string).collect()
+    }
+
+    fn context(flag: Option<&str>, separator: Option<&str>) -> Context {
+        Context {
+            platform_id: "Linux".into(),
+            c_compiler_id: "GNU".into(),
+            linker_wrapper_flag: flag.map(ToString::to_string),
+            linker_wrapper_flag_sep: separator.map(ToString::to_string),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn shell_quoting() {
+        assert_eq!(split_shell("-include foo.h"), ["-include", "foo.h"]);
+        assert_eq!(split_shell("  -a   -b "), ["-a", "-b"]);
+        assert_eq!(
+            split_shell(r#"-DNAME="foo bar" -D'X=a b' -Dq=\"x\""#),
+            ["-DNAME=foo bar", "-DX=a b", r#"-Dq="x""#]
+        );
+        assert_eq!(split_shell(r#""a\"b\n" '' x\ y"#), [r#"a"b\n"#, "", "x y"]);
+        assert!(split_shell("").is_empty());
+    }
+
+    #[test]
+    fn compile_options() {
+        assert_eq!(
+            expand_compile_options(strings(&[
+                "-Wall",
+                "SHELL:-include config.h",
+                "SHELL:-Xclang -fno-pch-timestamp"
+            ])),
+            [
+                "-Wall",
+                "-include",
+                "config.h",
+                "-Xclang",
+                "-fno-pch-timestamp"
+            ]
+        );
+    }
+
+    #[test]
+    fn linker_options() {
+        let options = strings(&[
+            "LINKER:-z,defs",
+            "LINKER:SHELL:--version-script foo.map",
+            "SHELL:-framework Cocoa",
+            "-pthread",
+        ]);
+
+        // GCC and Clang
+        assert_eq!(
+            expand_link_options(options.clone(), &context(Some("-Wl,"), Some(","))),
+            [
+                "-Wl,-z,defs",
+                "-Wl,--version-script,foo.map",
+                "-framework",
+                "Cocoa",
+                "-pthread"
+            ]
+        );
+
+        // No C compiler enabled
+        assert_eq!(
+            expand_link_options(options.clone(), &Context::default())[..2],
+            ["-Wl,-z,defs", "-Wl,--version-script,foo.map"]
+        );
+
+        // -Xlinker with the options as separate arguments
+        assert_eq!(
+            expand_link_options(options.clone(), &context(Some("-Xlinker; "), None))[..8],
+            [
+                "-Xlinker",
+                "-z",
+                "-Xlinker",
+                "defs",
+                "-Xlinker",
+                "--version-script",
+                "-Xlinker",
+                "foo.map"
+            ]
+        );
+
+        // MSVC passes the options to link.exe as they are
+        let msvc = Context {
+            platform_id: "Windows".into(),
+            c_compiler_id: "MSVC".into(),
+            ..Default::default()
+        };
+        assert_eq!(
+            expand_link_options(strings(&["LINKER:/NODEFAULTLIB:libcmt"]), &msvc),
+            ["/NODEFAULTLIB:libcmt"]
+        );
+        assert_eq!(
+            expand_link_options(strings(&["LINKER:/NODEFAULTLIB:libcmt,/DEBUG"]), &msvc),
+            ["/NODEFAULTLIB:libcmt", "/DEBUG"]
+        );
+        assert_eq!(
+            expand_link_options(strings(&["LINKER:/DEBUG"]), &context(Some(""), Some(""))),
+            ["/DEBUG"]
+        );
+    }
+}
@@ -0,0 +1,320 @@
+// SPDX-FileCopyrightText: 2024 Daniel Vrátil <dvratil@kde.org>
+//
+// SPDX-License-Identifier: MIT
+
+//! Support for cross-compilation with [CMake toolchain files][cmake_toolchains].
+//!
+//! Without a toolchain file, CMake searches for packages built for the host, which would then
+//! be linked into a binary for a different target. The toolchain file is either specified by
+//! the user, taken from the environment the same way as the [cmake][cmake_crate] crate does
+//! (`CMAKE_TOOLCHAIN_FILE_<target>`, `TARGET_CMAKE_TOOLCHAIN_FILE`, `CMAKE_TOOLCHAIN_FILE`),
+//! or generated from the Cargo target when cross-compiling.
+//!
+//! [cmake_toolchains]: https://cmake.org/cmake/help/latest/manual/cmake-toolchains.7.html
+//! [cmake_crate]: https://docs.rs/cmake/latest/cmake/
+
+use std::path::{Path, PathBuf};
+
+use crate::cmake::Error;
+use crate::compiler::var_names;
+
+/// Name of the generated toolchain file in the working directory. The path is relative, so that
+/// the arguments passed to CMake (and therefore the cache keys) don't depend on the working directory.
+const GENERATED_TOOLCHAIN_FILE: &str = "cmake-package-toolchain.cmake";
+
+/// Returns names of all environment variables that affect the toolchain.
+pub(crate) fn env_vars() -> Vec<String> {
+    let target = std::env::var("TARGET").ok();
+    let host = std::env::var("HOST").ok();
+    ["CMAKE_TOOLCHAIN_FILE", "CMAKE_SYSROOT"]
+        .iter()
+        .flat_map(|var| var_names(var, target.as_deref(), host.as_deref()))
+        .chain(
+            [
+                "TARGET",
+                "HOST",
+                "CARGO_CFG_TARGET_OS",
+                "CARGO_CFG_TARGET_ARCH",
+                "CARGO_CFG_TARGET_VENDOR",
+            ]
+            .map(ToString::to_string),
+        )
+        .collect()
+}
+
+/// Returns the toolchain file to use for the package, writing a generated one into the
+/// `working_directory` if needed. Returns `None` if no toolchain file is needed.
+pub(crate) fn setup(
+    working_directory: &Path,
+    requested: Option<PathBuf>,
+) -> Result<Option<PathBuf>, Error> {
+    if let Some(file) = self::requested(requested)? {
+        return Ok(Some(file));
+    }
+
+    let getenv = |var: &str| std::env::var(var).ok().filter(|value| !value.is_empty());
+    match generate(getenv, |path| path.is_dir()) {
+        Some(toolchain) => {
+            std::fs::write(working_directory.join(GENERATED_TOOLCHAIN_FILE), toolchain)
+                .map_err(Error::IO)?;
+            Ok(Some(GENERATED_TOOLCHAIN_FILE.into()))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Returns the toolchain file requested by the user, or in the environment, if any, without
+/// generating one.
+pub(crate) fn requested(requested: Option<PathBuf>) -> Result<Option<PathBuf>, Error> {
+    let getenv = |var: &str| std::env::var(var).ok().filter(|value| !value.is_empty());
+    requested_with(getenv, requested).map_err(Error::IO)
+}
+
+/// Same as [`requested()`], but with the environment variables looked up by `getenv`.
+///
+/// CMake runs in a temporary working directory, so a relative path is made absolute against
+/// the current directory of the build script.
+fn requested_with(
+    getenv: impl Fn(&str) -> Option<String>,
+    requested: Option<PathBuf>,
+) -> std::io::Result<Option<PathBuf>> {
+    requested
+        .or_else(|| lookup(getenv, "CMAKE_TOOLCHAIN_FILE").map(Into::into))
+        .map(std::path::absolute)
+        .transpose()
+}
+
+/// Looks up the target-specific variant of `var`, see [`var_names()`].
+fn lookup(getenv: impl Fn(&str) -> Option<String>, var: &str) -> Option<String> {
+    let target = getenv("TARGET");
+    let host = getenv("HOST");
+    var_names(var, target.as_deref(), host.as_deref())
+        .iter()
+        .find_map(|name| getenv(name))
+}
+
+/// Maps the `target_os` of the Cargo target to the [`CMAKE_SYSTEM_NAME`][cmake_system_name].
+///
+/// [cmake_system_name]: https://cmake.org/cmake/help/latest/variable/CMAKE_SYSTEM_NAME.html
+fn system_name(os: &str) -> String {
+    match os {
+        "linux" => "Linux",
+        "android" => "Android",
+        "windows" => "Windows",
+        "macos" => "Darwin",
+        "ios" => "iOS",
+        "tvos" => "tvOS",
+        "watchos" => "watchOS",
+        "visionos" => "visionOS",
+        "freebsd" => "FreeBSD",
+        "netbsd" => "NetBSD",
+        "openbsd" => "OpenBSD",
+        "dragonfly" => "DragonFly",
+        "solaris" | "illumos" => "SunOS",
+        "haiku" => "Haiku",
+        "emscripten" => "Emscripten",
+        "wasi" => "WASI",
+        "none" => "Generic",
+        os => os,
+    }
+    .to_string()
+}
+
+/// Maps the `target_arch` of the Cargo target to the [`CMAKE_SYSTEM_PROCESSOR`][cmake_system_processor].
+///
+/// [cmake_system_processor]: https://cmake.org/cmake/help/latest/variable/CMAKE_SYSTEM_PROCESSOR.html
+fn system_processor(arch: &str, vendor: &str) -> String {
+    match (arch, vendor) {
+        ("aarch64", "apple") => "arm64",
+        ("x86", _) => "i686",
+        (arch, _) => arch,
+    }
+    .to_string()
+}
+
+/// Generates a toolchain file for the Cargo target, if it differs from the host.
+///
+/// The sysroot is taken from the `CMAKE_SYSROOT` environment variable (or its target-specific
+/// variant). The sysroot and the multiarch directory of the target (e.g. `/usr/aarch64-linux-gnu`)
+/// are used as the root paths to search for the packages in, if they exist.
+fn generate(
+    getenv: impl Fn(&str) -> Option<String>,
+    is_dir: impl Fn(&Path) -> bool,
+) -> Option<String> {
+    let target = getenv("TARGET")?;
+    if getenv("HOST").is_some_and(|host| host == target) {
+        return None;
+    }
+    let os = getenv("CARGO_CFG_TARGET_OS")?;
+    let arch = getenv("CARGO_CFG_TARGET_ARCH")?;
+    let vendor = getenv("CARGO_CFG_TARGET_VENDOR").unwrap_or_default();
+
+    let system_name = system_name(&os);
+    let processor = system_processor(&arch, &vendor);
+    let mut toolchain = vec![
+        format!("set(CMAKE_SYSTEM_NAME {system_name})"),
+        format!("set(CMAKE_SYSTEM_PROCESSOR {processor})"),
+    ];
+    if vendor == "apple" {
+        toolchain.push(format!("set(CMAKE_OSX_ARCHITECTURES {processor})"));
+    }
+
+    let sysroot = lookup(&getenv, "CMAKE_SYSROOT");
+    if let Some(sysroot) = &sysroot {
+        toolchain.push(format!("set(CMAKE_SYSROOT \"{sysroot}\")"));
+    }
+    // The triple without the vendor, e.g. aarch64-linux-gnu for aarch64-unknown-linux-gnu
+    let multiarch = Path::new("/usr").join(
+        target
+            .split('-')
+            .filter(|part| *part != vendor)
+            .collect::<Vec<_>>()
+            .join("-"),
+    );
+    let root_paths = sysroot
+        .map(PathBuf::from)
+        .into_iter()
+        .chain(Some(multiarch).filter(|path| is_dir(path)))
+        .map(|path| format!("\"{}\"", path.display()))
+        .collect::<Vec<_>>();
+    if !root_paths.is_empty() {
+        toolchain.push(format!(
+            "set(CMAKE_FIND_ROOT_PATH {})",
+            root_paths.join(" ")
+        ));
+        // Search for the packages only in the target root paths, but for the programs on the host
+        toolchain.extend(
+            ["LIBRARY", "INCLUDE", "PACKAGE"]
+                .iter()
+                .map(|kind| format!("set(CMAKE_FIND_ROOT_PATH_MODE_{kind} ONLY)")),
+        );
+        toolchain.push("set(CMAKE_FIND_ROOT_PATH_MODE_PROGRAM NEVER)".to_string());
+    }
+    Some(toolchain.join("\n") + "\n")
+}
+
+#[cfg(test)]
+mod testing {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn generate_with(env: &[(&str, &str)], dirs: &[&str]) -> Option<String> {
+        let env: HashMap<&str, &str> = env.iter().copied().collect();
+        generate(
+            |var| env.get(var).map(ToString::to_string),
+            |path| dirs.iter().any(|dir| Path::new(dir) == path),
+        )
+    }
+
+    #[test]
+    fn native_build() {
+        assert_eq!(
+            generate_with(
+                &[
+                    ("TARGET", "x86_64-unknown-linux-gnu"),
+                    ("HOST", "x86_64-unknown-linux-gnu"),
+                    ("CARGO_CFG_TARGET_OS", "linux"),
+                    ("CARGO_CFG_TARGET_ARCH", "x86_64"),
+                ],
+                &[]
+            ),
+            None
+        );
+        assert_eq!(generate_with(&[], &[]), None);
+    }
+
+    #[test]
+    fn cross_linux() {
+        let env = [
+            ("TARGET", "aarch64-unknown-linux-gnu"),
+            ("HOST", "x86_64-unknown-linux-gnu"),
+            ("CARGO_CFG_TARGET_OS", "linux"),
+            ("CARGO_CFG_TARGET_ARCH", "aarch64"),
+            ("CARGO_CFG_TARGET_VENDOR", "unknown"),
+        ];
+        assert_eq!(
+            generate_with(&env, &[]).unwrap(),
+            "set(CMAKE_SYSTEM_NAME Linux)\nset(CMAKE_SYSTEM_PROCESSOR aarch64)\n"
+        );
+        assert_eq!(
+            generate_with(
+                &[
+                    &env[..],
+                    &[("CMAKE_SYSROOT_aarch64_unknown_linux_gnu", "/opt/sysroot")]
+                ]
+                .concat(),
+                &["/usr/aarch64-linux-gnu"]
+            )
+            .unwrap(),
+            [
+                "set(CMAKE_SYSTEM_NAME Linux)",
+                "set(CMAKE_SYSTEM_PROCESSOR aarch64)",
+                "set(CMAKE_SYSROOT \"/opt/sysroot\")",
+                "set(CMAKE_FIND_ROOT_PATH \"/opt/sysroot\" \"/usr/aarch64-linux-gnu\")",
+                "set(CMAKE_FIND_ROOT_PATH_MODE_LIBRARY ONLY)",
+                "set(CMAKE_FIND_ROOT_PATH_MODE_INCLUDE ONLY)",
+                "set(CMAKE_FIND_ROOT_PATH_MODE_PACKAGE ONLY)",
+                "set(CMAKE_FIND_ROOT_PATH_MODE_PROGRAM NEVER)",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn cross_apple() {
+        assert_eq!(
+            generate_with(
+                &[
+                    ("TARGET", "aarch64-apple-ios"),
+                    ("HOST", "aarch64-apple-darwin"),
+                    ("CARGO_CFG_TARGET_OS", "ios"),
+                    ("CARGO_CFG_TARGET_ARCH", "aarch64"),
+                    ("CARGO_CFG_TARGET_VENDOR", "apple"),
+                ],
+                &[]
+            )
+            .unwrap(),
+            "set(CMAKE_SYSTEM_NAME iOS)\nset(CMAKE_SYSTEM_PROCESSOR arm64)\nset(CMAKE_OSX_ARCHITECTURES arm64)\n"
+        );
+    }
+
+    #[test]
+    fn toolchain_file_from_env() {
+        let env: HashMap<&str, &str> = [
+            ("TARGET", "armv7-unknown-linux-gnueabihf"),
+            ("HOST", "x86_64-unknown-linux-gnu"),
+            ("CMAKE_TOOLCHAIN_FILE", "/opt/generic.cmake"),
+            ("TARGET_CMAKE_TOOLCHAIN_FILE", "/opt/armv7.cmake"),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(
+            lookup(
+                |var| env.get(var).map(ToString::to_string),
+                "CMAKE_TOOLCHAIN_FILE"
+            ),
+            Some("/opt/armv7.cmake".to_string())
+        );
+    }
+
+    #[test]
+    fn relative_toolchain_file() {
+        let cwd = std::env::current_dir().unwrap();
+        let getenv = |var: &str| (var == "CMAKE_TOOLCHAIN_FILE").then(|| "env.cmake".to_string());
+        assert_eq!(
+            requested_with(getenv, None).unwrap(),
+            Some(cwd.join("env.cmake"))
+        );
+        assert_eq!(
+            requested_with(getenv, Some("cmake/user.cmake".into())).unwrap(),
+            Some(cwd.join("cmake/user.cmake"))
+        );
+        assert_eq!(
+            requested_with(getenv, Some("/opt/user.cmake".into())).unwrap(),
+            Some(PathBuf::from("/opt/user.cmake"))
+        );
+        assert_eq!(requested_with(|_| None, None).unwrap(), None);
+    }
+}
@@ -17,6 +17,17 @@ pub enum VersionError {
     VersionTooOld(Version),
 }
 
+impl std::fmt::Display for VersionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VersionError::InvalidVersion => write!(f, "invalid version"),
+            VersionError::VersionTooOld(version) => write!(f, "version {} is too old", version),
+        }
+    }
+}
+
+impl std::error::Error for VersionError {}
+
 impl Version {
     pub fn parse(version: &str) -> Result<Version, VersionError> {
         let parts: Vec<&str> = version.split('.').collect();
@@ -1,4 +1,4 @@
-use cmake_package::{find_cmake, find_package, Error, Version};
+use cmake_package::{find_cmake, find_package, Error, TargetType, Version};
 use scopeguard::defer;
 use serial_test::serial;
 
@@ -78,6 +78,8 @@ fn test_find_openssl_release() {
         .target("OpenSSL::SSL")
         .expect("Failed to find OpenSSL::SSL target");
     assert_eq!(target.name, "OpenSSL::SSL");
+    // FindOpenSSL creates the targets without knowing whether the libraries are static or shared
+    assert_eq!(target.target_type, TargetType::UnknownLibrary);
     if cfg!(target_os = "linux") {
         assert_eq!(target.include_directories, ["/usr/include"]);
         assert_eq!(target.link_libraries.len(), 2);
@@ -86,11 +88,11 @@ fn test_find_openssl_release() {
         assert!(target
             .link_libraries
             .iter()
-            .any(|lib| lib.contains("libcrypto.so")));
+            .any(|lib| lib.item.contains("libcrypto.so")));
         assert!(target
             .link_libraries
             .iter()
-            .any(|lib| lib.contains("libssl.so")));
+            .any(|lib| lib.item.contains("libssl.so")));
     } else if cfg!(target_os = "windows") {
         assert_eq!(
             target.include_directories,
@@ -100,14 +102,44 @@ fn test_find_openssl_release() {
         assert!(target
             .link_libraries
             .iter()
-            .any(|lib| lib.contains("libcrypto64MD.lib")));
+            .any(|lib| lib.item.contains("libcrypto64MD.lib")));
         assert!(target
             .link_libraries
             .iter()
-            .any(|lib| lib.contains("libssl64MD.lib")));
+            .any(|lib| lib.item.contains("libssl64MD.lib")));
     }
 }
 
+#[test]
+#[serial]
+#[cfg_attr(target_os = "windows", ignore = "Requires OpenSSL installed")]
+fn test_find_openssl_missing_target() {
+    let _tmpdir = common::set_outdir();
+
+    let package = find_package("OpenSSL")
+        .verbose()
+        .find()
+        .expect("Failed to find OpenSSL");
+
+    match package
+        .try_target("OpenSSL::ThisTargetDoesNotExist")
+        .expect_err("Found a target that cannot exist")
+    {
+        Error::TargetNotFound {
+            package, target, ..
+        } => {
+            assert_eq!(package, "OpenSSL");
+            assert_eq!(target, "OpenSSL::ThisTargetDoesNotExist");
+        }
+        err => panic!("Unexpected error: expected TargetNotFound, got {:?}", err),
+    }
+
+    let targets = package.targets(&["OpenSSL::SSL", "OpenSSL::ThisTargetDoesNotExist"]);
+    assert_eq!(targets.len(), 2);
+    assert!(targets[0].is_some());
+    assert!(targets[1].is_none());
+}
+
 #[test]
 #[serial]
 #[ignore = "Requires Qt installed"]
@@ -146,7 +178,7 @@ fn test_find_qt_release() {
             assert!(core.compile_definitions.contains(&def.to_string()));
         }
         for lib in ["Qt6Core.lib", "mpr", "userenv"] {
-            assert!(core.link_libraries.iter().any(|l| l.contains(lib)))
+            assert!(core.link_libraries.iter().any(|l| l.item.contains(lib)))
         }
         assert_eq!(
             core.include_directories,
@@ -169,7 +201,7 @@ fn test_find_qt_release() {
             ["QT_CORE_LIB".to_string(), "QT_GUI_LIB".to_string()]
         );
         for lib in ["libQt6Core.so", "libQt6Gui.so", "libOpenGL.so", "libGLX.so"] {
-            assert!(gui.link_libraries.iter().any(|l| l.contains(lib)))
+            assert!(gui.link_libraries.iter().any(|l| l.item.contains(lib)))
         }
         assert_eq!(
             gui.include_directories,
@@ -186,7 +218,7 @@ fn test_find_qt_release() {
             assert!(gui.compile_definitions.contains(&def.to_string()));
         }
         for lib in ["Qt6Gui.lib", "Qt6Core.lib", "mpr", "userenv"] {
-            assert!(gui.link_libraries.iter().any(|l| l.contains(lib)))
+            assert!(gui.link_libraries.iter().any(|l| l.item.contains(lib)))
         }
         assert_eq!(
             gui.include_directories,
@@ -198,6 +230,23 @@ fn test_find_qt_release() {
             ]
         )
     }
+
+    let moc = package
+        .executable("Qt6::moc")
+        .expect("Failed to find Qt6::moc executable");
+    assert!(moc.location.is_file());
+    assert!(moc
+        .command()
+        .arg("--version")
+        .status()
+        .expect("Failed to run moc")
+        .success());
+    match package.executable("Qt6::Core") {
+        Err(Error::NotAnExecutable { target_type, .. }) => {
+            assert_eq!(target_type, TargetType::SharedLibrary)
+        }
+        other => panic!("Unexpected result: {:?}", other),
+    }
 }
 
 #[test]
@@ -225,7 +274,7 @@ fn test_find_qt_debug() {
             assert!(core.compile_definitions.contains(&def.to_string()));
         }
         for lib in ["Qt6Cored.lib", "mpr", "userenv"] {
-            assert!(core.link_libraries.iter().any(|l| l.contains(lib)))
+            assert!(core.link_libraries.iter().any(|l| l.item.contains(lib)))
         }
         assert_eq!(
             core.include_directories,